@@ -0,0 +1,187 @@
+use std::sync::Arc;
+use serde::Deserialize;
+use serde_json::json;
+use teloxide::Bot;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+use crate::{ AppState, BotState };
+use crate::hosts::HostEntry;
+use crate::http::{ Request, Response };
+
+// everything the api routes share with the telegram handlers
+#[derive(Clone)]
+pub struct Api {
+    pub bot: Bot,
+    pub bot_state: Arc<Mutex<BotState>>,
+    pub app_state: Arc<Mutex<AppState>>,
+    pub token: Option<String>,
+}
+
+// body of POST and DELETE /api/hosts
+#[derive(Deserialize)]
+struct HostRequest {
+    // a hosts file line for POST, the address for DELETE
+    host: String,
+}
+
+// json api served by the http task, every route requires "Authorization: Bearer <api_token>"
+pub async fn handle(request: Request, api: Api) -> Response {
+    let Some(token) = &api.token else {
+        return Response::text(403, "api_token is not configured");
+    };
+    if request.bearer_token() != Some(token.as_str()) {
+        return Response::text(401, "invalid or missing bearer token");
+    }
+
+    let path = request.path.split('?').next().unwrap_or_default();
+    match (request.method.as_str(), path) {
+        ("GET", "/api/hosts") => hosts(&api.app_state).await,
+        ("POST", "/api/hosts") => add_host(&api, &request.body).await,
+        ("DELETE", "/api/hosts") => remove_host(&api, &request.body).await,
+        (_, "/api/hosts") => Response::text(405, "method not allowed"),
+        ("GET", "/api/status") => status(&api).await,
+        (_, "/api/status") => Response::text(405, "method not allowed"),
+        ("POST", "/api/monitoring/start") => start(&api).await,
+        ("POST", "/api/monitoring/stop") => stop(&api).await,
+        (_, "/api/monitoring/start" | "/api/monitoring/stop") => Response::text(405, "method not allowed"),
+        _ => Response::text(404, "not found"),
+    }
+}
+
+// full monitoring state of every host
+async fn hosts(app_state: &Arc<Mutex<AppState>>) -> Response {
+    let app_state_guard = app_state.lock().await;
+    let mut hosts = app_state_guard.hosts.iter().collect::<Vec<_>>();
+    hosts.sort_by_key(|(address, _)| *address);
+
+    let hosts = hosts
+        .into_iter()
+        .map(|(address, host)| {
+            let state = &host.state;
+            json!({
+                "name": address,
+                "label": host.entry.label,
+                "critical": host.entry.critical,
+                "online": state.online,
+                "pending": state.pending,
+                "snoozed_until": state.snoozed_until.filter(|_| state.snoozed()),
+                "offline_since": state.offline_since,
+                "failures": state.failures,
+                "acknowledged": state.acknowledged,
+                "degraded": state.degraded,
+                "last_latency_ms": state.current_latency(),
+                "average_latency_ms": state.average_latency(),
+                "packet_loss": state.packet_loss,
+            })
+        })
+        .collect::<Vec<_>>();
+    Response::json(200, json!(hosts))
+}
+
+fn parse_host_request(body: &str) -> Result<String, Response> {
+    serde_json::from_str::<HostRequest>(body)
+        .map(|request| request.host)
+        .map_err(|e| Response::text(400, &format!("expected {{\"host\": \"...\"}} => {}", e)))
+}
+
+// same as /add, the host is checked once before it is added
+async fn add_host(api: &Api, body: &str) -> Response {
+    let line = match parse_host_request(body) {
+        Ok(line) => line,
+        Err(response) => {
+            return response;
+        }
+    };
+    let entry = match HostEntry::parse(&line) {
+        Ok(entry) => entry,
+        Err(e) => {
+            return Response::text(400, &format!("invalid host: {}", e));
+        }
+    };
+    let address = entry.address.clone();
+    match crate::add_host(&api.bot_state, &api.app_state, entry).await {
+        Ok(result) =>
+            Response::json(
+                201,
+                json!({
+                    "name": address,
+                    "status": result.status,
+                    "output": result.output,
+                    "latency_ms": result.latency,
+                    "packet_loss": result.packet_loss,
+                })
+            ),
+        Err(crate::AddHostError::Refused(reason)) => Response::text(409, &reason),
+        Err(crate::AddHostError::Write(reason)) => Response::text(500, &reason),
+    }
+}
+
+// same as /remove without the confirmation
+async fn remove_host(api: &Api, body: &str) -> Response {
+    let address = match parse_host_request(body) {
+        Ok(address) => address,
+        Err(response) => {
+            return response;
+        }
+    };
+    match crate::remove_host(&api.app_state, &address).await {
+        Ok(true) => Response::json(200, json!({ "name": address })),
+        Ok(false) => Response::text(404, &format!("host '{}' not found", address)),
+        Err(reason) => Response::text(500, &reason),
+    }
+}
+
+// whether monitoring runs and how many hosts are down
+async fn status(api: &Api) -> Response {
+    let (running, paused, silenced, chat_id, ping_interval, last_cycle) = {
+        let bot_state_guard = api.bot_state.lock().await;
+        (
+            bot_state_guard.task.is_some(),
+            bot_state_guard.paused,
+            bot_state_guard.silenced,
+            bot_state_guard.chat_id.map(|chat_id| chat_id.0),
+            bot_state_guard.ping_interval(),
+            bot_state_guard.last_cycle.map(|last_cycle| last_cycle.elapsed().as_secs()),
+        )
+    };
+    let (hosts, offline) = {
+        let app_state_guard = api.app_state.lock().await;
+        let offline = app_state_guard.hosts
+            .values()
+            .filter(|host| !host.state.online)
+            .count();
+        (app_state_guard.hosts.len(), offline)
+    };
+    Response::json(
+        200,
+        json!({
+            "running": running,
+            "paused": paused,
+            "silenced": silenced,
+            "chat_id": chat_id,
+            "ping_interval": ping_interval,
+            "last_cycle_secs_ago": last_cycle,
+            "hosts": hosts,
+            "offline": offline,
+        })
+    )
+}
+
+// same as /start, notifications go to the chat that last ran /start
+async fn start(api: &Api) -> Response {
+    let Some(chat_id) = api.bot_state.lock().await.config.last_chat_id else {
+        return Response::text(409, "no chat has run /start yet");
+    };
+    if crate::start_monitoring(&api.bot, &api.bot_state, &api.app_state, ChatId(chat_id)).await {
+        Response::json(200, json!({ "running": true, "chat_id": chat_id }))
+    } else {
+        Response::text(409, "monitoring is already running")
+    }
+}
+
+async fn stop(api: &Api) -> Response {
+    match crate::stop_monitoring(&api.bot_state).await {
+        Ok(()) => Response::json(200, json!({ "running": false })),
+        Err(reason) => Response::text(409, reason),
+    }
+}
@@ -0,0 +1,641 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{ Duration, Instant };
+use futures::stream::{ self, StreamExt };
+use ipnet::IpNet;
+use serde::{ Serialize, Deserialize };
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use crate::BotConfig;
+use crate::host_state::{ parse_ping_latency, parse_ping_loss };
+use crate::hosts::{ CheckMethod, HostEntry };
+use crate::nmap::{ self, ScanPrivileges };
+use crate::runner::CommandRunner;
+
+// time allowed for connecting, sending the probe and reading the response each
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+// response bytes searched for the expected string
+const TCP_MAX_RESPONSE: usize = 4096;
+// time allowed for a whole http request
+pub const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+// addresses of a subnet checked at the same time
+const SUBNET_CONCURRENCY: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Up,
+    // host did not answer
+    Down,
+    // host accepted the connection but the service did not respond as expected
+    Unhealthy,
+    // the check itself could not run, eg. ping is missing, says nothing about the host
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckOutcome {
+    pub status: CheckStatus,
+    // command output or reason of the failure
+    pub output: String,
+    // round trip time in ms when the host answered
+    pub latency: Option<f64>,
+    // percentage of lost ping packets, only known for ping checks
+    pub packet_loss: Option<f64>,
+}
+
+// how the monitoring loop checks plain hosts without a tcp:// or http(s):// address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MonitorMethod {
+    #[default]
+    Ping,
+    // nmap connect scan of a few common ports, for networks that filter icmp
+    Nmap,
+}
+
+// second check of a plain host that failed its check, the host is only offline when both fail,
+// written as "nmap", "tcp:<port>" or "none" in config.toml and the hosts file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Fallback {
+    #[default]
+    None,
+    // nmap reachability scan like monitor_method = "nmap", works where icmp is filtered
+    Nmap,
+    // tcp connect to a port the host is known to listen on, eg. 443
+    Tcp(u16),
+}
+
+impl std::str::FromStr for Fallback {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "none" => Ok(Fallback::None),
+            "nmap" => Ok(Fallback::Nmap),
+            _ => {
+                let port = text
+                    .strip_prefix("tcp:")
+                    .ok_or(format!("expected none, nmap or tcp:<port>, got '{}'", text))?;
+                port.parse::<u16>().map(Fallback::Tcp).map_err(|e| format!("invalid port '{}': {}", port, e))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Fallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fallback::None => write!(f, "none"),
+            Fallback::Nmap => write!(f, "nmap"),
+            Fallback::Tcp(port) => write!(f, "tcp:{}", port),
+        }
+    }
+}
+
+impl TryFrom<String> for Fallback {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Fallback> for String {
+    fn from(fallback: Fallback) -> Self {
+        fallback.to_string()
+    }
+}
+
+// trust and client certificate for http checks against an internal pki, [tls_profiles.<name>] in config.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TlsProfile {
+    // pem bundle of ca certificates trusted in addition to the system roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<PathBuf>,
+    // pem client certificate and its pkcs8 pem key for mutual tls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+}
+
+impl TlsProfile {
+    fn client(&self) -> Result<reqwest::Client, String> {
+        let read = |path: &PathBuf| std::fs::read(path).map_err(|e| format!("unable to read {} => {}", path.display(), e));
+        let mut builder = reqwest::Client::builder().timeout(HTTP_TIMEOUT);
+        if let Some(ca_file) = &self.ca_file {
+            let certificates = reqwest::Certificate
+                ::from_pem_bundle(&read(ca_file)?)
+                .map_err(|e| format!("invalid ca bundle {} => {}", ca_file.display(), e))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        match (&self.cert_file, &self.key_file) {
+            (Some(cert_file), Some(key_file)) => {
+                let identity = reqwest::Identity
+                    ::from_pkcs8_pem(&read(cert_file)?, &read(key_file)?)
+                    .map_err(|e| format!("invalid client certificate {} => {}", cert_file.display(), e))?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => {
+                return Err("cert_file and key_file must be set together".to_string());
+            }
+        }
+        builder.build().map_err(|e| e.to_string())
+    }
+}
+
+// http clients for the checks, the default one trusts the system roots
+#[derive(Clone)]
+pub struct HttpClients {
+    default: reqwest::Client,
+    // by profile name, profiles that failed to load hold the reason
+    profiles: HashMap<String, Result<reqwest::Client, String>>,
+}
+
+impl HttpClients {
+    pub fn new(profiles: &HashMap<String, TlsProfile>) -> Self {
+        let default = reqwest::Client::builder().timeout(HTTP_TIMEOUT).build().unwrap_or_default();
+        let profiles = profiles
+            .iter()
+            .map(|(name, profile)| (name.clone(), profile.client()))
+            .collect();
+        HttpClients { default, profiles }
+    }
+
+    fn get(&self, profile: Option<&str>) -> Result<&reqwest::Client, String> {
+        let Some(name) = profile else {
+            return Ok(&self.default);
+        };
+        match self.profiles.get(name) {
+            Some(Ok(client)) => Ok(client),
+            Some(Err(e)) => Err(format!("tls profile '{}' failed to load: {}", name, e)),
+            None => Err(format!("tls profile '{}' is not in [tls_profiles]", name)),
+        }
+    }
+}
+
+// reasons the configured tls profiles can't be used, checked by /config validate and at startup
+pub fn validate_tls_profiles(profiles: &HashMap<String, TlsProfile>) -> Vec<String> {
+    profiles
+        .iter()
+        .filter_map(|(name, profile)| profile.client().err().map(|e| format!("[tls_profiles.{}] {}", name, e)))
+        .collect()
+}
+
+// check a host with its configured method
+pub async fn check_host(
+    runner: &dyn CommandRunner,
+    entry: &HostEntry,
+    config: &BotConfig,
+    http_clients: &HttpClients
+) -> CheckOutcome {
+    match &entry.method {
+        CheckMethod::Ping => ping_host(runner, entry, &entry.address, config).await,
+        CheckMethod::Subnet { network } => subnet_outcome(network, &check_subnet(runner, entry, config).await),
+        CheckMethod::Tcp { host, port, send, expect } => tcp(host, *port, send.as_deref(), expect.as_deref()).await,
+        CheckMethod::Http { url, expected_status, contains, tls } =>
+            match http_clients.get(tls.as_deref()) {
+                Ok(client) => http(client, url, *expected_status, contains.as_deref()).await,
+                Err(e) =>
+                    CheckOutcome {
+                        status: CheckStatus::Error,
+                        output: e,
+                        latency: None,
+                        packet_loss: None,
+                    },
+            }
+    }
+}
+
+// check a plain host or one address of a subnet with monitor_method and the fallback of entry
+async fn ping_host(runner: &dyn CommandRunner, entry: &HostEntry, address: &str, config: &BotConfig) -> CheckOutcome {
+    let outcome = if config.monitor_method == MonitorMethod::Nmap {
+        nmap_reachability(runner, address, config.scan_privileges).await
+    } else {
+        ping(runner, &config.ping_args, address).await.unwrap_or_else(|e| CheckOutcome {
+            status: CheckStatus::Error,
+            output: format!("unable to run ping => {}", e),
+            latency: None,
+            packet_loss: None,
+        })
+    };
+    if outcome.status != CheckStatus::Down {
+        return outcome;
+    }
+    fallback(runner, entry, address, config, outcome).await
+}
+
+// status of every address of a subnet entry in address order, empty for other hosts
+pub async fn check_subnet(runner: &dyn CommandRunner, entry: &HostEntry, config: &BotConfig) -> Vec<(String, CheckStatus)> {
+    let CheckMethod::Subnet { network } = &entry.method else {
+        return Vec::new();
+    };
+    stream
+        ::iter(network.hosts().map(|address| address.to_string()))
+        .map(|address| async move {
+            let outcome = ping_host(runner, entry, &address, config).await;
+            (address, outcome.status)
+        })
+        .buffered(SUBNET_CONCURRENCY)
+        .collect().await
+}
+
+// the subnet is up while any of its addresses answers, eg. "3/14 hosts of 10.0.0.0/28 up"
+pub fn subnet_outcome(network: &IpNet, members: &[(String, CheckStatus)]) -> CheckOutcome {
+    let up = members
+        .iter()
+        .filter(|(_, status)| *status == CheckStatus::Up)
+        .count();
+    let status = if up > 0 {
+        CheckStatus::Up
+    } else if !members.is_empty() && members.iter().all(|(_, status)| *status == CheckStatus::Error) {
+        CheckStatus::Error
+    } else {
+        CheckStatus::Down
+    };
+    CheckOutcome {
+        status,
+        output: format!("{}/{} hosts of {} up", up, members.len(), network),
+        latency: None,
+        packet_loss: None,
+    }
+}
+
+// retry a plain host that didn't answer with its fallback, keeps the failed outcome unless the fallback reaches it
+async fn fallback(
+    runner: &dyn CommandRunner,
+    entry: &HostEntry,
+    address: &str,
+    config: &BotConfig,
+    failed: CheckOutcome
+) -> CheckOutcome {
+    let fallback = entry.fallback.unwrap_or(config.fallback);
+    let outcome = match fallback {
+        Fallback::None => {
+            return failed;
+        }
+        Fallback::Nmap => nmap_reachability(runner, address, config.scan_privileges).await,
+        Fallback::Tcp(port) => tcp(address, port, None, None).await,
+    };
+    if outcome.status != CheckStatus::Up {
+        return failed;
+    }
+    // latency of another method would skew the ping latency history
+    CheckOutcome {
+        status: CheckStatus::Up,
+        output: format!("primary check failed, fallback {} answered: {}", fallback, outcome.output),
+        latency: None,
+        packet_loss: None,
+    }
+}
+
+// external command check_host runs for a host, None for tcp and http checks that don't run one
+pub fn check_command(entry: &HostEntry, config: &BotConfig) -> Option<(&'static str, Vec<String>)> {
+    match &entry.method {
+        CheckMethod::Ping if config.monitor_method == MonitorMethod::Nmap => {
+            Some(nmap::command_line(config.scan_privileges.reachability_args(), &entry.address, config.scan_privileges))
+        }
+        CheckMethod::Ping => Some(ping_command(&config.ping_args, &entry.address)),
+        CheckMethod::Tcp { .. } | CheckMethod::Http { .. } | CheckMethod::Subnet { .. } => None,
+    }
+}
+
+pub async fn nmap_reachability(runner: &dyn CommandRunner, host: &str, privileges: ScanPrivileges) -> CheckOutcome {
+    match nmap::scan(runner, privileges.reachability_args(), host, privileges).await {
+        Ok(output) =>
+            CheckOutcome {
+                status: if nmap::host_answered(&output) { CheckStatus::Up } else { CheckStatus::Down },
+                output,
+                latency: None,
+                packet_loss: None,
+            },
+        Err(e) =>
+            CheckOutcome {
+                status: CheckStatus::Error,
+                output: e,
+                latency: None,
+                packet_loss: None,
+            },
+    }
+}
+
+fn ping_command(args: &[String], host: &str) -> (&'static str, Vec<String>) {
+    // "--" so a host can never be read as an option
+    let mut args = args.to_vec();
+    args.push("--".to_string());
+    args.push(host.to_string());
+    ("ping", args)
+}
+
+pub async fn ping(runner: &dyn CommandRunner, args: &[String], host: &str) -> std::io::Result<CheckOutcome> {
+    let (program, args) = ping_command(args, host);
+    let output = runner.run(program, &args).await?;
+    Ok(CheckOutcome {
+        status: if output.success { CheckStatus::Up } else { CheckStatus::Down },
+        latency: parse_ping_latency(&output.stdout),
+        packet_loss: parse_ping_loss(&output.stdout),
+        // ping prints errors like an unknown host or invalid argument on stderr only
+        output: if output.stdout.trim().is_empty() { output.stderr } else { output.stdout },
+    })
+}
+
+pub async fn tcp(host: &str, port: u16, send: Option<&str>, expect: Option<&str>) -> CheckOutcome {
+    let target = format!("{}:{}", host, port);
+    let start = Instant::now();
+    let mut stream = match timeout(TCP_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            return CheckOutcome {
+                status: CheckStatus::Down,
+                output: format!("connection to {} failed: {}", target, e),
+                latency: None,
+                packet_loss: None,
+            };
+        }
+        Err(_) => {
+            return CheckOutcome {
+                status: CheckStatus::Down,
+                output: format!("connection to {} timed out", target),
+                latency: None,
+                packet_loss: None,
+            };
+        }
+    };
+    let latency = Some(start.elapsed().as_secs_f64() * 1000.0);
+
+    if let Some(send) = send {
+        let sent = timeout(TCP_TIMEOUT, stream.write_all(send.as_bytes())).await;
+        if !matches!(sent, Ok(Ok(()))) {
+            return CheckOutcome {
+                status: CheckStatus::Unhealthy,
+                output: format!("sending probe to {} failed", target),
+                latency,
+                packet_loss: None,
+            };
+        }
+    }
+
+    let Some(expect) = expect else {
+        return CheckOutcome {
+            status: CheckStatus::Up,
+            output: format!("connected to {}", target),
+            latency,
+            packet_loss: None,
+        };
+    };
+
+    // read until the expected string shows up, the connection closes or the timeout runs out
+    let mut response = Vec::new();
+    let mut buf = [0u8; 1024];
+    let read = timeout(TCP_TIMEOUT, async {
+        while response.len() < TCP_MAX_RESPONSE {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    response.extend_from_slice(&buf[..n]);
+                    if String::from_utf8_lossy(&response).contains(expect) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }).await;
+
+    let response = String::from_utf8_lossy(&response).trim().to_string();
+    if matches!(read, Ok(true)) {
+        CheckOutcome {
+            status: CheckStatus::Up,
+            output: response,
+            latency,
+            packet_loss: None,
+        }
+    } else {
+        CheckOutcome {
+            status: CheckStatus::Unhealthy,
+            output: format!("{} did not respond with '{}', got '{}'", target, expect, response),
+            latency,
+            packet_loss: None,
+        }
+    }
+}
+
+pub async fn http(
+    client: &reqwest::Client,
+    url: &str,
+    expected_status: Option<u16>,
+    contains: Option<&str>
+) -> CheckOutcome {
+    let start = Instant::now();
+    let response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return CheckOutcome {
+                status: CheckStatus::Down,
+                output: format!("request to {} failed: {}", url, e),
+                latency: None,
+                packet_loss: None,
+            };
+        }
+    };
+    let latency = Some(start.elapsed().as_secs_f64() * 1000.0);
+
+    let status = response.status();
+    let status_ok = match expected_status {
+        Some(expected) => status.as_u16() == expected,
+        None => status.is_success(),
+    };
+    if !status_ok {
+        return CheckOutcome {
+            status: CheckStatus::Unhealthy,
+            output: format!("{} returned status {}", url, status),
+            latency,
+            packet_loss: None,
+        };
+    }
+
+    if let Some(contains) = contains {
+        let body = response.text().await.unwrap_or_default();
+        if !body.contains(contains) {
+            return CheckOutcome {
+                status: CheckStatus::Unhealthy,
+                output: format!("{} response does not contain '{}'", url, contains),
+                latency,
+                packet_loss: None,
+            };
+        }
+    }
+
+    CheckOutcome {
+        status: CheckStatus::Up,
+        output: format!("{} returned status {}", url, status),
+        latency,
+        packet_loss: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_command_ends_options_before_host() {
+        let (program, args) = ping_command(&["-c".to_string(), "3".to_string()], "-oN");
+        assert_eq!(program, "ping");
+        assert_eq!(args, ["-c", "3", "--", "-oN"]);
+    }
+
+    const PING_UP: &str =
+        "3 packets transmitted, 3 received, 0% packet loss, time 2003ms\nrtt min/avg/max/mdev = 0.045/0.052/0.060/0.007 ms";
+    const PING_DOWN: &str = "3 packets transmitted, 0 received, 100% packet loss, time 2040ms";
+
+    // answers ping and nmap for the hosts in up, other hosts don't answer
+    struct FakeRunner {
+        up: Vec<&'static str>,
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run<'a>(&'a self, program: &'a str, args: &'a [String]) -> crate::runner::RunFuture<'a> {
+            Box::pin(async move {
+                let host = args.last().map(String::as_str).unwrap_or_default();
+                let up = self.up.contains(&host);
+                let stdout = match program {
+                    "ping" if up => PING_UP.to_string(),
+                    "ping" => PING_DOWN.to_string(),
+                    _ if up => "PORT   STATE  SERVICE\n22/tcp open   ssh".to_string(),
+                    _ => "Note: Host seems down.".to_string(),
+                };
+                // nmap exits with 0 for a host that is down
+                Ok(crate::runner::CommandOutput { success: up || program != "ping", stdout, stderr: String::new() })
+            })
+        }
+    }
+
+    // fails every command as if the program was not installed
+    struct MissingRunner;
+
+    impl CommandRunner for MissingRunner {
+        fn run<'a>(&'a self, program: &'a str, _args: &'a [String]) -> crate::runner::RunFuture<'a> {
+            Box::pin(async move { Err(std::io::Error::new(std::io::ErrorKind::NotFound, program.to_string())) })
+        }
+    }
+
+    async fn check(runner: &dyn CommandRunner, line: &str, config: &BotConfig) -> CheckOutcome {
+        let entry = HostEntry::parse(line).unwrap();
+        check_host(runner, &entry, config, &HttpClients::new(&HashMap::new())).await
+    }
+
+    #[tokio::test]
+    async fn ping_host_up_reports_latency_and_loss() {
+        let outcome = check(&FakeRunner { up: vec!["10.0.0.5"] }, "10.0.0.5", &BotConfig::default()).await;
+        assert_eq!(outcome.status, CheckStatus::Up);
+        assert_eq!(outcome.latency, Some(0.052));
+        assert_eq!(outcome.packet_loss, Some(0.0));
+        assert_eq!(outcome.output, PING_UP);
+    }
+
+    #[tokio::test]
+    async fn ping_host_down_keeps_ping_output() {
+        let outcome = check(&FakeRunner { up: Vec::new() }, "10.0.0.5", &BotConfig::default()).await;
+        assert_eq!(outcome.status, CheckStatus::Down);
+        assert_eq!(outcome.latency, None);
+        assert_eq!(outcome.packet_loss, Some(100.0));
+        assert_eq!(outcome.output, PING_DOWN);
+    }
+
+    #[tokio::test]
+    async fn missing_ping_is_an_error_not_an_outage() {
+        let outcome = check(&MissingRunner, "10.0.0.5", &BotConfig::default()).await;
+        assert_eq!(outcome.status, CheckStatus::Error);
+        assert!(outcome.output.starts_with("unable to run ping"));
+    }
+
+    #[tokio::test]
+    async fn missing_nmap_is_an_error_not_an_outage() {
+        let config = BotConfig { monitor_method: MonitorMethod::Nmap, ..BotConfig::default() };
+        let outcome = check(&MissingRunner, "10.0.0.5", &config).await;
+        assert_eq!(outcome.status, CheckStatus::Error);
+        assert!(outcome.output.starts_with("unable to run"));
+    }
+
+    #[tokio::test]
+    async fn nmap_monitor_method() {
+        let config = BotConfig { monitor_method: MonitorMethod::Nmap, ..BotConfig::default() };
+        let outcome = check(&FakeRunner { up: vec!["10.0.0.5"] }, "10.0.0.5", &config).await;
+        assert_eq!(outcome.status, CheckStatus::Up);
+        let outcome = check(&FakeRunner { up: Vec::new() }, "10.0.0.5", &config).await;
+        assert_eq!(outcome.status, CheckStatus::Down);
+    }
+
+    #[tokio::test]
+    async fn fallback_answers_for_a_failed_ping() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let line = format!("127.0.0.1 fallback=tcp:{}", port);
+        let outcome = check(&FakeRunner { up: Vec::new() }, &line, &BotConfig::default()).await;
+        assert_eq!(outcome.status, CheckStatus::Up);
+        assert_eq!(outcome.latency, None);
+        assert!(outcome.output.starts_with(&format!("primary check failed, fallback tcp:{} answered", port)));
+
+        // a fallback that fails too keeps the ping outcome
+        let outcome = check(&FakeRunner { up: Vec::new() }, "127.0.0.1 fallback=nmap", &BotConfig::default()).await;
+        assert_eq!(outcome.status, CheckStatus::Down);
+        assert_eq!(outcome.output, PING_DOWN);
+    }
+
+    #[tokio::test]
+    async fn subnet_is_up_while_any_address_answers() {
+        let runner = FakeRunner { up: vec!["10.0.0.2"] };
+        let outcome = check(&runner, "10.0.0.0/30", &BotConfig::default()).await;
+        assert_eq!(outcome.status, CheckStatus::Up);
+        assert_eq!(outcome.output, "1/2 hosts of 10.0.0.0/30 up");
+
+        let entry = HostEntry::parse("10.0.0.0/30").unwrap();
+        let members = check_subnet(&runner, &entry, &BotConfig::default()).await;
+        assert_eq!(
+            members,
+            [("10.0.0.1".to_string(), CheckStatus::Down), ("10.0.0.2".to_string(), CheckStatus::Up)]
+        );
+        let outcome = check(&FakeRunner { up: Vec::new() }, "10.0.0.0/30", &BotConfig::default()).await;
+        assert_eq!(outcome.status, CheckStatus::Down);
+        let outcome = check(&MissingRunner, "10.0.0.0/30", &BotConfig::default()).await;
+        assert_eq!(outcome.status, CheckStatus::Error);
+    }
+
+    #[tokio::test]
+    async fn tcp_check_connects_and_expects() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(b"220 mail ready\r\n").await;
+            }
+        });
+        let config = BotConfig::default();
+        let outcome = check(&MissingRunner, &format!("tcp://127.0.0.1:{} expect=220", port), &config).await;
+        assert_eq!(outcome.status, CheckStatus::Up);
+        assert_eq!(outcome.output, "220 mail ready");
+        assert!(outcome.latency.is_some());
+        let outcome = check(&MissingRunner, &format!("tcp://127.0.0.1:{} expect=PONG", port), &config).await;
+        assert_eq!(outcome.status, CheckStatus::Unhealthy);
+    }
+
+    #[test]
+    fn check_command_per_method() {
+        let config = BotConfig::default();
+        let entry = HostEntry::parse("10.0.0.5").unwrap();
+        assert_eq!(check_command(&entry, &config).unwrap().0, "ping");
+        let entry = HostEntry::parse("tcp://10.0.0.5:22").unwrap();
+        assert!(check_command(&entry, &config).is_none());
+        let config = BotConfig { monitor_method: MonitorMethod::Nmap, ..BotConfig::default() };
+        let entry = HostEntry::parse("10.0.0.5").unwrap();
+        let (_, args) = check_command(&entry, &config).unwrap();
+        assert_eq!(args.last().unwrap(), "10.0.0.5");
+    }
+}
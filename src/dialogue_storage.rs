@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use log::{ error, info };
+use serde::{ Serialize, de::DeserializeOwned };
+use teloxide::dispatching::dialogue::Storage;
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+// dialogue storage kept in a json file so prompts like /add survive a restart
+pub struct JsonFileStorage<D> {
+    path: PathBuf,
+    dialogues: Mutex<HashMap<i64, D>>,
+}
+
+impl<D: DeserializeOwned> JsonFileStorage<D> {
+    // read saved dialogues, a missing or broken file just means no saved dialogues
+    pub fn open(path: PathBuf) -> Arc<Self> {
+        let dialogues = match std::fs::read_to_string(&path) {
+            Ok(content) =>
+                serde_json::from_str(&content).unwrap_or_else(|e| {
+                    error!("Unable to parse dialogues from {} => {}", path.display(), e);
+                    HashMap::new()
+                }),
+            Err(_) => {
+                info!("No dialogue file at {}, starting fresh", path.display());
+                HashMap::new()
+            }
+        };
+        Arc::new(JsonFileStorage { path, dialogues: Mutex::new(dialogues) })
+    }
+}
+
+impl<D: Serialize> JsonFileStorage<D> {
+    async fn save(&self, dialogues: &HashMap<i64, D>) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(dialogues).map_err(std::io::Error::other)?;
+        // a crash mid-write must not leave a truncated file that open would discard
+        crate::hosts::write_atomic(&self.path, &json)
+    }
+}
+
+impl<D> Storage<D>
+    for JsonFileStorage<D>
+    where D: Serialize + DeserializeOwned + Clone + Send + Sync + 'static
+{
+    type Error = std::io::Error;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<Result<(), Self::Error>> {
+        Box::pin(async move {
+            let mut dialogues = self.dialogues.lock().await;
+            if dialogues.remove(&chat_id.0).is_some() {
+                self.save(&dialogues).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn update_dialogue(self: Arc<Self>, chat_id: ChatId, dialogue: D) -> BoxFuture<Result<(), Self::Error>> {
+        Box::pin(async move {
+            let mut dialogues = self.dialogues.lock().await;
+            dialogues.insert(chat_id.0, dialogue);
+            self.save(&dialogues).await
+        })
+    }
+
+    fn get_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<Result<Option<D>, Self::Error>> {
+        Box::pin(async move { Ok(self.dialogues.lock().await.get(&chat_id.0).cloned()) })
+    }
+}
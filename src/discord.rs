@@ -0,0 +1,20 @@
+use serde_json::json;
+use crate::split_message;
+
+// discord rejects message content longer than this
+const DISCORD_MAX_MESSAGE_LENGTH: usize = 2000;
+
+// post text to a discord webhook, split into several messages when too long
+pub async fn send(client: &reqwest::Client, webhook_url: &str, text: &str) -> Result<(), String> {
+    for chunk in split_message(text, DISCORD_MAX_MESSAGE_LENGTH) {
+        let response = client
+            .post(webhook_url)
+            .json(&json!({ "content": chunk }))
+            .send().await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("webhook returned status {}", response.status()));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{ AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor };
+use serde::{ Serialize, Deserialize };
+use crate::messages::Severity;
+
+// smtp settings for email notifications, [email] section of config.toml
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EmailConfig {
+    pub smtp_server: String,
+    // 465 (implicit tls) when unset, other ports use starttls
+    pub smtp_port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    // notifications of this severity and above are emailed
+    #[serde(default = "default_min_severity")]
+    pub min_severity: Severity,
+}
+
+fn default_min_severity() -> Severity {
+    Severity::Critical
+}
+
+pub async fn send(config: &EmailConfig, subject: &str, body: &str) -> Result<(), String> {
+    let from = config.from.parse::<Mailbox>().map_err(|e| format!("invalid from address: {}", e))?;
+    let mut builder = Message::builder().from(from).subject(subject);
+    for to in &config.to {
+        builder = builder.to(to.parse::<Mailbox>().map_err(|e| format!("invalid to address {}: {}", to, e))?);
+    }
+    let message = builder.body(body.to_string()).map_err(|e| e.to_string())?;
+
+    let relay = match config.smtp_port {
+        Some(port) if port != 465 => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_server),
+        _ => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_server),
+    };
+    let mut transport = relay.map_err(|e| e.to_string())?;
+    if let Some(port) = config.smtp_port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    transport
+        .build()
+        .send(message).await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,36 @@
+use std::sync::atomic::{ AtomicBool, Ordering };
+use crate::http::{ Request, Response };
+
+// telegram updates are being dispatched, set around the dispatcher in main
+static DISPATCHER_RUNNING: AtomicBool = AtomicBool::new(false);
+// config.toml was loaded at startup and the last write of it succeeded
+static CONFIG_OK: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dispatcher_running(running: bool) {
+    DISPATCHER_RUNNING.store(running, Ordering::Relaxed);
+}
+
+pub fn set_config_ok(ok: bool) {
+    CONFIG_OK.store(ok, Ordering::Relaxed);
+}
+
+// liveness probe for docker and kubernetes served on health_bind, needs no token
+pub async fn handle(request: Request) -> Response {
+    let path = request.path.split('?').next().unwrap_or_default();
+    match (request.method.as_str(), path) {
+        ("GET", "/healthz") => {}
+        (_, "/healthz") => {
+            return Response::text(405, "method not allowed");
+        }
+        _ => {
+            return Response::text(404, "not found");
+        }
+    }
+    if !DISPATCHER_RUNNING.load(Ordering::Relaxed) {
+        return Response::text(503, "dispatcher is not running");
+    }
+    if !CONFIG_OK.load(Ordering::Relaxed) {
+        return Response::text(503, "config.toml could not be loaded or written");
+    }
+    Response::text(200, "ok")
+}
@@ -0,0 +1,445 @@
+use std::collections::{ BTreeMap, BTreeSet, HashMap, VecDeque };
+use std::path::Path;
+use chrono::{ DateTime, Utc };
+use log::{ error, info, warn };
+use serde::{ Serialize, Deserialize };
+use crate::hosts::Host;
+
+// monitoring state of a single host, persisted so alerting survives restarts
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct HostState {
+    pub online: bool,
+    pub offline_since: Option<DateTime<Utc>>,
+    // consecutive failed checks
+    pub failures: u32,
+    // current outage was acknowledged with /ack, cleared on recovery
+    pub acknowledged: bool,
+    // last time a still offline reminder was sent for the current outage
+    pub last_reminder: Option<DateTime<Utc>>,
+    // most recent average round trip times in ms, oldest first
+    pub latency_history: VecDeque<f64>,
+    // latency is above the configured threshold while the host is online
+    pub degraded: bool,
+    // current outage began while the host was expected down, nothing was sent for it
+    pub expected_outage: bool,
+    // packet loss percentage of the last ping check
+    pub packet_loss: Option<f64>,
+    // packet loss is above the configured threshold while the host is online
+    pub lossy: bool,
+    // finished outages of the last Retention::outage_days, oldest first
+    pub outages: VecDeque<Outage>,
+    // open ports of the last nmap scan that found the host up, compared on recovery by recovery_port_check
+    pub open_ports: Option<BTreeSet<String>>,
+    // times the host went offline or recovered within the flap window, oldest first
+    pub transitions: VecDeque<DateTime<Utc>>,
+    // changed state too often, alerts are held back until it is stable for a flap window
+    pub flapping: bool,
+    // not checked since the bot or the monitoring task started, online is only assumed until then
+    #[serde(skip)]
+    pub pending: bool,
+    // last Retention::state_changes times the host went offline or came back, oldest first, shown by /history
+    pub history: VecDeque<StateChange>,
+    // notifications of the host are dropped until then, set with /snooze, state is still tracked
+    pub snoozed_until: Option<DateTime<Utc>>,
+    // addresses of a subnet that answered the last check
+    pub subnet_up: BTreeSet<String>,
+    // addresses of a subnet that answered before and stopped, with when they stopped
+    pub subnet_down: BTreeMap<String, DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Outage {
+    pub start: DateTime<Utc>,
+    pub duration_secs: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StateChange {
+    pub time: DateTime<Utc>,
+    pub online: bool,
+}
+
+// how much history is kept, [retention] in config.toml, applied by HostState::prune
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Retention {
+    // latency samples per host, the average in /latency is taken over them
+    pub latency_samples: usize,
+    // outages older than this are dropped, also the period /top covers
+    pub outage_days: i64,
+    // outages kept per host however recent, bounds the history of a flapping host
+    pub max_outages: usize,
+    // /status and /diff scans kept for /stats
+    pub scan_history: usize,
+    // online and offline changes kept per host for /history
+    pub state_changes: usize,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            latency_samples: 10,
+            outage_days: 30,
+            max_outages: 500,
+            scan_history: 20,
+            state_changes: 100,
+        }
+    }
+}
+
+// change in latency or packet loss state caused by a new sample
+pub enum LatencyChange {
+    Degraded,
+    Normal,
+}
+impl Default for HostState {
+    fn default() -> Self {
+        HostState {
+            online: true,
+            offline_since: None,
+            failures: 0,
+            acknowledged: false,
+            last_reminder: None,
+            latency_history: VecDeque::new(),
+            degraded: false,
+            expected_outage: false,
+            packet_loss: None,
+            lossy: false,
+            outages: VecDeque::new(),
+            open_ports: None,
+            transitions: VecDeque::new(),
+            flapping: false,
+            history: VecDeque::new(),
+            pending: true,
+            snoozed_until: None,
+            subnet_up: BTreeSet::new(),
+            subnet_down: BTreeMap::new(),
+        }
+    }
+}
+
+impl HostState {
+    // record a failed check, returns true if the host just went offline
+    pub fn mark_offline(&mut self) -> bool {
+        self.failures += 1;
+        self.pending = false;
+        self.degraded = false;
+        self.lossy = false;
+        self.packet_loss = None;
+        if self.online {
+            self.online = false;
+            let now = Utc::now();
+            self.offline_since = Some(now);
+            self.history.push_back(StateChange { time: now, online: false });
+            return true;
+        }
+        false
+    }
+
+    // returns how long the host has been offline if an unacknowledged outage is due a reminder
+    pub fn reminder_due(&mut self, interval: chrono::Duration) -> Option<chrono::Duration> {
+        if self.online || self.acknowledged || interval.is_zero() {
+            return None;
+        }
+        let now = Utc::now();
+        let offline_since = self.offline_since.unwrap_or(now);
+        let last = self.last_reminder.unwrap_or(offline_since);
+        if now - last < interval {
+            return None;
+        }
+        self.last_reminder = Some(now);
+        Some(now - offline_since)
+    }
+
+    // record a latency sample, threshold of 0 disables degraded detection
+    pub fn record_latency(&mut self, latency: f64, threshold: f64) -> Option<LatencyChange> {
+        self.latency_history.push_back(latency);
+
+        let degraded = threshold > 0.0 && latency > threshold;
+        if degraded == self.degraded {
+            return None;
+        }
+        self.degraded = degraded;
+        Some(if degraded { LatencyChange::Degraded } else { LatencyChange::Normal })
+    }
+
+    // record the packet loss of a check the host answered, threshold of 0 disables lossy detection
+    pub fn record_packet_loss(&mut self, loss: f64, threshold: f64) -> Option<LatencyChange> {
+        self.packet_loss = Some(loss);
+        let lossy = threshold > 0.0 && loss > threshold;
+        if lossy == self.lossy {
+            return None;
+        }
+        self.lossy = lossy;
+        Some(if lossy { LatencyChange::Degraded } else { LatencyChange::Normal })
+    }
+
+    pub fn current_latency(&self) -> Option<f64> {
+        self.latency_history.back().copied()
+    }
+
+    pub fn average_latency(&self) -> Option<f64> {
+        if self.latency_history.is_empty() {
+            return None;
+        }
+        Some(self.latency_history.iter().sum::<f64>() / (self.latency_history.len() as f64))
+    }
+
+    // record a successful check, returns how long the host was offline if it just recovered
+    pub fn mark_online(&mut self) -> Option<chrono::Duration> {
+        self.failures = 0;
+        self.pending = false;
+        if self.online {
+            return None;
+        }
+        self.online = true;
+        self.acknowledged = false;
+        self.last_reminder = None;
+        let now = Utc::now();
+        self.history.push_back(StateChange { time: now, online: true });
+        let offline_since = self.offline_since.take();
+        let downtime = offline_since.map(|since| now - since).unwrap_or_default();
+        if let Some(start) = offline_since {
+            self.outages.push_back(Outage { start, duration_secs: downtime.num_seconds() });
+        }
+        Some(downtime)
+    }
+
+    pub fn snoozed(&self) -> bool {
+        self.snoozed_until.is_some_and(|until| until > Utc::now())
+    }
+
+    // forget the current outage, acknowledgement and flapping so the next check starts from a clean baseline,
+    // history and latency samples are kept
+    pub fn reset_alarms(&mut self) {
+        self.online = true;
+        self.offline_since = None;
+        self.failures = 0;
+        self.acknowledged = false;
+        self.last_reminder = None;
+        self.expected_outage = false;
+        self.degraded = false;
+        self.lossy = false;
+        self.transitions.clear();
+        self.flapping = false;
+        self.subnet_down.clear();
+    }
+
+    // record which addresses of a subnet answered, returns the addresses that stopped answering and those that
+    // came back with how long they were down, addresses never seen up are unused and not reported
+    pub fn record_members(&mut self, members: &[(String, bool)]) -> (Vec<String>, Vec<(String, chrono::Duration)>) {
+        let now = Utc::now();
+        let mut went_down = Vec::new();
+        let mut came_back = Vec::new();
+        for (member, up) in members {
+            if *up {
+                if let Some(since) = self.subnet_down.remove(member) {
+                    came_back.push((member.clone(), now - since));
+                }
+                self.subnet_up.insert(member.clone());
+            } else if self.subnet_up.remove(member) {
+                self.subnet_down.insert(member.clone(), now);
+                went_down.push(member.clone());
+            }
+        }
+        (went_down, came_back)
+    }
+
+    // drop history beyond the retention limits, oldest first
+    pub fn prune(&mut self, retention: &Retention) {
+        let excess = self.latency_history.len().saturating_sub(retention.latency_samples);
+        self.latency_history.drain(..excess);
+        let cutoff = Utc::now() - chrono::Duration::days(retention.outage_days);
+        self.outages.retain(|outage| outage.start >= cutoff);
+        let excess = self.outages.len().saturating_sub(retention.max_outages);
+        self.outages.drain(..excess);
+        let excess = self.history.len().saturating_sub(retention.state_changes);
+        self.history.drain(..excess);
+    }
+
+    // record a change between online and offline, returns true if the host just started flapping
+    pub fn record_transition(&mut self, threshold: usize, window: chrono::Duration) -> bool {
+        self.transitions.push_back(Utc::now());
+        self.expire_transitions(window);
+        if self.flapping || self.transitions.len() <= threshold {
+            return false;
+        }
+        self.flapping = true;
+        true
+    }
+
+    // returns true if a flapping host had no change for a whole window and is stable again
+    pub fn flap_ended(&mut self, window: chrono::Duration) -> bool {
+        self.expire_transitions(window);
+        if !self.flapping || !self.transitions.is_empty() {
+            return false;
+        }
+        self.flapping = false;
+        true
+    }
+
+    fn expire_transitions(&mut self, window: chrono::Duration) {
+        let cutoff = Utc::now() - window;
+        while self.transitions.front().is_some_and(|time| *time < cutoff) {
+            self.transitions.pop_front();
+        }
+    }
+
+    // number of outages and total downtime in the history, including an ongoing outage
+    pub fn outage_totals(&self, days: i64) -> (usize, chrono::Duration) {
+        let cutoff = Utc::now() - chrono::Duration::days(days);
+        let mut count = 0;
+        let mut downtime = chrono::Duration::zero();
+        for outage in self.outages.iter().filter(|outage| outage.start >= cutoff) {
+            count += 1;
+            downtime += chrono::Duration::seconds(outage.duration_secs);
+        }
+        if let Some(since) = self.offline_since {
+            count += 1;
+            downtime += Utc::now() - since;
+        }
+        (count, downtime)
+    }
+}
+
+// read saved host states, a missing or broken state file just means no saved state
+pub fn load_state(path: &Path) -> HashMap<String, HostState> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => {
+            info!("No host state file at {}, starting fresh", path.display());
+            return HashMap::new();
+        }
+    };
+    match serde_json::from_str(&content) {
+        Ok(state) => state,
+        Err(e) => {
+            warn!("Unable to parse host state from {}, starting fresh => {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+pub fn save_state(path: &Path, hosts: &HashMap<String, Host>) {
+    let states = hosts
+        .iter()
+        .map(|(address, host)| (address, &host.state))
+        .collect::<HashMap<_, _>>();
+    let json = match serde_json::to_string_pretty(&states) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Unable to serialize host state => {}", e);
+            return;
+        }
+    };
+    if let Err(e) = crate::hosts::write_atomic(path, &json) {
+        error!("Unable to write host state to {} => {}", path.display(), e);
+    }
+}
+
+// average round trip time in ms from ping output
+// eg. "rtt min/avg/max/mdev = 0.045/0.052/0.060/0.007 ms"
+pub fn parse_ping_latency(stdout: &str) -> Option<f64> {
+    let line = stdout.lines().find(|line| line.contains("min/avg/max"))?;
+    let values = line.split('=').nth(1)?;
+    values.trim().split('/').nth(1)?.trim().parse().ok()
+}
+
+// percentage from the "3 packets transmitted, 2 received, 33.3333% packet loss" summary line
+pub fn parse_ping_loss(stdout: &str) -> Option<f64> {
+    let line = stdout.lines().find(|line| line.contains("packet loss"))?;
+    let field = line.split(',').find(|field| field.contains("packet loss"))?;
+    field.trim().split('%').next()?.trim().parse().ok()
+}
+
+// human readable duration eg. "1h 4m 10s"
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let total = duration.num_seconds().max(0);
+    let (hours, minutes, seconds) = (total / 3600, (total % 3600) / 60, total % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+// duration written as a number with an optional s, m, h or d unit eg. "90", "10m", "2h", seconds without unit
+pub fn parse_duration(text: &str) -> Option<chrono::Duration> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text, "s"),
+    };
+    let number = number.parse::<i64>().ok()?;
+    match unit.to_ascii_lowercase().as_str() {
+        "s" => chrono::Duration::try_seconds(number),
+        "m" => chrono::Duration::try_minutes(number),
+        "h" => chrono::Duration::try_hours(number),
+        "d" => chrono::Duration::try_days(number),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_keeps_newest_entries_in_order() {
+        let retention = Retention {
+            latency_samples: 3,
+            outage_days: 30,
+            max_outages: 4,
+            scan_history: 20,
+            state_changes: 5,
+        };
+        let mut state = HostState::default();
+        let now = Utc::now();
+        for i in 0..10 {
+            state.latency_history.push_back(i as f64);
+            // an outage every four days, the oldest three are past outage_days
+            state.outages.push_back(Outage { start: now - chrono::Duration::days(40 - i * 4), duration_secs: i });
+            state.history.push_back(StateChange { time: now - chrono::Duration::minutes(10 - i), online: i % 2 == 0 });
+        }
+        state.prune(&retention);
+
+        assert_eq!(state.latency_history, [7.0, 8.0, 9.0]);
+        assert_eq!(
+            state.outages
+                .iter()
+                .map(|outage| outage.duration_secs)
+                .collect::<Vec<_>>(),
+            [6, 7, 8, 9]
+        );
+        assert_eq!(
+            state.history
+                .iter()
+                .map(|change| change.online)
+                .collect::<Vec<_>>(),
+            [false, true, false, true, false]
+        );
+        assert!(state.history.iter().zip(state.history.iter().skip(1)).all(|(a, b)| a.time < b.time));
+        assert_eq!(state.history.back().unwrap().time, now - chrono::Duration::minutes(1));
+    }
+
+    #[test]
+    fn prune_drops_outages_older_than_outage_days() {
+        let retention = Retention { outage_days: 7, ..Retention::default() };
+        let mut state = HostState::default();
+        let now = Utc::now();
+        for days in [30, 10, 6, 1] {
+            state.outages.push_back(Outage { start: now - chrono::Duration::days(days), duration_secs: days });
+        }
+        state.prune(&retention);
+        assert_eq!(
+            state.outages
+                .iter()
+                .map(|outage| outage.duration_secs)
+                .collect::<Vec<_>>(),
+            [6, 1]
+        );
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::{ HashMap, HashSet };
+use std::path::Path;
+use ipnet::IpNet;
+use log::error;
+use serde::{ Serialize, Deserialize };
+use crate::checks::Fallback;
+use crate::host_state::HostState;
+use crate::maintenance::Schedule;
+use crate::MIN_PING_INTERVAL;
+
+// a hosts file larger than this is refused instead of read into memory
+const MAX_HOSTS_FILE_SIZE: u64 = 10 * 1024 * 1024;
+// every address of a subnet is pinged on each check, so subnets are limited to 256 addresses, eg. /24
+const MAX_SUBNET_HOST_BITS: u8 = 8;
+
+// how the monitoring loop checks a host
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CheckMethod {
+    // icmp ping with the configured ping_args
+    #[default]
+    Ping,
+    // tcp connect, optionally sending a probe and expecting a string in the response
+    Tcp {
+        host: String,
+        port: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        send: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expect: Option<String>,
+    },
+    // http get, fails on an unexpected status code or if the body doesn't contain a string
+    Http {
+        url: String,
+        // any 2xx status when unset
+        #[serde(rename = "status", default, skip_serializing_if = "Option::is_none")]
+        expected_status: Option<u16>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        contains: Option<String>,
+        // name of a [tls_profiles] entry in config.toml with a private ca or client certificate
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tls: Option<String>,
+    },
+    // every address of a cidr range pinged like a plain host, alerts are sent per address
+    Subnet {
+        network: IpNet,
+    },
+}
+
+// host definition from one line of hosts.txt, eg.
+// 192.168.1.1
+// 10.0.0.5 Office-Router note=contact%20netops%20before%20rebooting
+// 10.0.0.1 Core-Router interval=15
+// 10.0.0.20 Workstation expected_down=22:00-07:00
+// tcp://mail.example.com:25 expect=220
+// tcp://10.0.0.5:6379 send=PING%0D%0A expect=PONG
+// https://example.com/health status=200 contains=ok
+// https://intranet.local/health tls=internal
+// 10.0.0.0/28 Office-LAN
+// 10.0.0.1 Core-Router critical=true
+// 10.0.0.8 Firewall fallback=tcp:443
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostEntry {
+    // first token of the line, identifies the host in commands and notifications
+    pub address: String,
+    // friendly name shown in notifications, words on the line that are not option=value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    // free text shown in /status and offline alerts, set with /note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    // seconds between checks of this host, the global ping_interval when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+    // daily hours the host is normally off, being offline then doesn't alert
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_down: Option<Schedule>,
+    // alerts for the host get through /silence and quiet hours, set with /critical
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub critical: bool,
+    // second check before a plain host counts as offline, the global fallback when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<Fallback>,
+    #[serde(default)]
+    pub method: CheckMethod,
+}
+
+impl HostEntry {
+    pub fn parse(line: &str) -> Result<HostEntry, String> {
+        let mut tokens = line.split_whitespace();
+        let address = tokens.next().ok_or("empty host")?.to_string();
+
+        let mut options = HashMap::new();
+        let mut label_words = Vec::new();
+        for token in tokens {
+            match token.split_once('=') {
+                Some((key, value)) => {
+                    options.insert(key.to_string(), percent_decode(value)?);
+                }
+                None => label_words.push(token),
+            }
+        }
+        let label = (!label_words.is_empty()).then(|| label_words.join(" "));
+        let note = options.remove("note");
+        let interval = options
+            .remove("interval")
+            .map(|interval| interval.parse::<u64>())
+            .transpose()
+            .map_err(|e| format!("invalid interval: {}", e))?;
+        let expected_down = options
+            .remove("expected_down")
+            .map(|schedule| Schedule::parse(&schedule))
+            .transpose()
+            .map_err(|e| format!("invalid expected_down: {}", e))?;
+        let critical = options
+            .remove("critical")
+            .map(|critical| critical.parse::<bool>())
+            .transpose()
+            .map_err(|e| format!("invalid critical: {}", e))?
+            .unwrap_or_default();
+        let fallback = options
+            .remove("fallback")
+            .map(|fallback| fallback.parse::<Fallback>())
+            .transpose()
+            .map_err(|e| format!("invalid fallback: {}", e))?;
+
+        let method = match address.strip_prefix("tcp://") {
+            Some(target) => {
+                let (host, port) = target
+                    .rsplit_once(':')
+                    .ok_or(format!("missing port in '{}'", address))?;
+                let port = port.parse::<u16>().map_err(|e| format!("invalid port '{}': {}", port, e))?;
+                // ipv6 addresses are written in brackets, eg. tcp://[::1]:22
+                let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+                if host.is_empty() {
+                    return Err(format!("missing host in '{}'", address));
+                }
+                CheckMethod::Tcp {
+                    host,
+                    port,
+                    send: options.remove("send"),
+                    expect: options.remove("expect"),
+                }
+            }
+            None if address.starts_with("http://") || address.starts_with("https://") => {
+                reqwest::Url::parse(&address).map_err(|e| format!("invalid url '{}': {}", address, e))?;
+                let expected_status = options
+                    .remove("status")
+                    .map(|status| status.parse::<u16>())
+                    .transpose()
+                    .map_err(|e| format!("invalid status: {}", e))?;
+                CheckMethod::Http {
+                    url: address.clone(),
+                    expected_status,
+                    contains: options.remove("contains"),
+                    tls: options.remove("tls"),
+                }
+            }
+            None if address.contains('/') => {
+                let network = address.parse::<IpNet>().map_err(|e| format!("invalid subnet '{}': {}", address, e))?;
+                CheckMethod::Subnet { network }
+            }
+            None => CheckMethod::Ping,
+        };
+        if let Some(key) = options.keys().next() {
+            return Err(format!("unknown option '{}' for {}", key, address));
+        }
+
+        let entry = HostEntry { address, label, note, interval, expected_down, critical, fallback, method };
+        entry.validate()?;
+        Ok(entry)
+    }
+
+    // line written back to hosts.txt
+    pub fn to_line(&self) -> String {
+        let mut line = self.address.clone();
+        if let Some(label) = &self.label {
+            line.push(' ');
+            line.push_str(label);
+        }
+        match &self.method {
+            CheckMethod::Ping | CheckMethod::Subnet { .. } => {}
+            CheckMethod::Tcp { send, expect, .. } => {
+                if let Some(send) = send {
+                    line.push_str(&format!(" send={}", percent_encode(send)));
+                }
+                if let Some(expect) = expect {
+                    line.push_str(&format!(" expect={}", percent_encode(expect)));
+                }
+            }
+            CheckMethod::Http { expected_status, contains, tls, .. } => {
+                if let Some(status) = expected_status {
+                    line.push_str(&format!(" status={}", status));
+                }
+                if let Some(contains) = contains {
+                    line.push_str(&format!(" contains={}", percent_encode(contains)));
+                }
+                if let Some(tls) = tls {
+                    line.push_str(&format!(" tls={}", percent_encode(tls)));
+                }
+            }
+        }
+        if let Some(interval) = self.interval {
+            line.push_str(&format!(" interval={}", interval));
+        }
+        if let Some(schedule) = &self.expected_down {
+            line.push_str(&format!(" expected_down={}", schedule));
+        }
+        if self.critical {
+            line.push_str(" critical=true");
+        }
+        if let Some(fallback) = self.fallback {
+            line.push_str(&format!(" fallback={}", fallback));
+        }
+        if let Some(note) = &self.note {
+            line.push_str(&format!(" note={}", percent_encode(note)));
+        }
+        line
+    }
+
+    // "label (address)" for labeled hosts, otherwise the address
+    pub fn display_name(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{} ({})", label, self.address),
+            None => self.address.clone(),
+        }
+    }
+
+    // checks shared by text lines and deserialized json or yaml entries
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval.is_some_and(|interval| interval < MIN_PING_INTERVAL) {
+            return Err(format!("interval must be at least {} seconds", MIN_PING_INTERVAL));
+        }
+        if let CheckMethod::Subnet { network } = &self.method {
+            if *network != network.trunc() {
+                return Err(format!("'{}' is not a network address, use {}", self.address, network.trunc()));
+            }
+            if network.max_prefix_len() - network.prefix_len() > MAX_SUBNET_HOST_BITS {
+                return Err(
+                    format!(
+                        "subnet '{}' is too large, at most /{} is allowed",
+                        self.address,
+                        network.max_prefix_len() - MAX_SUBNET_HOST_BITS
+                    )
+                );
+            }
+        }
+        if self.fallback.is_some() && !matches!(self.method, CheckMethod::Ping | CheckMethod::Subnet { .. }) {
+            return Err(format!("fallback only applies to pinged hosts, not {}", self.address));
+        }
+        self.validate_target()
+    }
+
+    // the address and scan target are passed to ping and nmap, a leading '-' would make them an option
+    pub fn validate_target(&self) -> Result<(), String> {
+        if self.address.starts_with('-') || self.scan_target().starts_with('-') {
+            return Err(format!("host '{}' must not start with '-'", self.address));
+        }
+        Ok(())
+    }
+
+    // hostname or ip used for nmap scans
+    pub fn scan_target(&self) -> String {
+        match &self.method {
+            CheckMethod::Ping => self.address.clone(),
+            CheckMethod::Tcp { host, .. } => host.clone(),
+            // nmap scans cidr ranges as is
+            CheckMethod::Subnet { network } => network.to_string(),
+            CheckMethod::Http { url, .. } =>
+                reqwest::Url
+                    ::parse(url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(|host| host.trim_matches(['[', ']']).to_string()))
+                    .unwrap_or_else(|| self.address.clone()),
+        }
+    }
+}
+
+// monitored host, definition from hosts.txt and state from the monitoring loop
+#[derive(Debug, Clone)]
+pub struct Host {
+    pub entry: HostEntry,
+    pub state: HostState,
+}
+
+// storage format of the hosts file, picked by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostsFormat {
+    // one host per line, hosts.txt
+    Text,
+    // list of host entries, hosts.json
+    Json,
+    // list of host entries, hosts.yaml or hosts.yml
+    Yaml,
+}
+
+impl HostsFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => HostsFormat::Json,
+            Some("yaml" | "yml") => HostsFormat::Yaml,
+            _ => HostsFormat::Text,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" | "txt" => Some(HostsFormat::Text),
+            "json" => Some(HostsFormat::Json),
+            "yaml" | "yml" => Some(HostsFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            HostsFormat::Text => "txt",
+            HostsFormat::Json => "json",
+            HostsFormat::Yaml => "yaml",
+        }
+    }
+}
+
+// read host definitions, invalid lines are logged and skipped
+pub fn load_hosts(path: &Path) -> std::io::Result<Vec<HostEntry>> {
+    let size = std::fs::metadata(path)?.len();
+    if size > MAX_HOSTS_FILE_SIZE {
+        return Err(
+            std::io::Error::other(format!("file is {} bytes, the limit is {} bytes", size, MAX_HOSTS_FILE_SIZE))
+        );
+    }
+    let content = std::fs::read_to_string(path)?;
+    let parsed = parse_hosts(&content, HostsFormat::from_path(path)).map_err(std::io::Error::other)?;
+    for (line, e) in parsed.invalid {
+        error!("Skipping invalid host '{}' in {} => {}", line, path.display(), e);
+    }
+
+    let mut seen = HashSet::new();
+    Ok(
+        parsed.entries
+            .into_iter()
+            .filter(|entry| seen.insert(entry.address.clone()))
+            .collect()
+    )
+}
+
+pub struct ParsedHosts {
+    pub entries: Vec<HostEntry>,
+    // invalid lines of a text file with the reason
+    pub invalid: Vec<(String, String)>,
+}
+
+// host definitions from file content, invalid text lines are returned instead of failing
+pub fn parse_hosts(content: &str, format: HostsFormat) -> Result<ParsedHosts, String> {
+    match format {
+        HostsFormat::Text => {
+            let mut entries = Vec::new();
+            let mut invalid = Vec::new();
+            for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                match HostEntry::parse(line) {
+                    Ok(entry) => entries.push(entry),
+                    Err(e) => invalid.push((line.to_string(), e)),
+                }
+            }
+            Ok(ParsedHosts { entries, invalid })
+        }
+        HostsFormat::Json =>
+            serde_json
+                ::from_str(content)
+                .map(validate_entries)
+                .map_err(|e| e.to_string()),
+        HostsFormat::Yaml =>
+            serde_yaml
+                ::from_str(content)
+                .map(validate_entries)
+                .map_err(|e| e.to_string()),
+    }
+}
+
+// checks HostEntry::parse does for text lines that deserializing structured entries skips
+fn validate_entries(entries: Vec<HostEntry>) -> ParsedHosts {
+    let mut valid = Vec::new();
+    let mut invalid = Vec::new();
+    for entry in entries {
+        match entry.validate() {
+            Ok(()) => valid.push(entry),
+            Err(e) => invalid.push((entry.address, e)),
+        }
+    }
+    ParsedHosts { entries: valid, invalid }
+}
+
+// write all hosts to the hosts file, in the format given by its extension
+pub fn save_hosts(path: &Path, hosts: &HashMap<String, Host>) -> std::io::Result<()> {
+    let mut entries = hosts
+        .values()
+        .map(|host| &host.entry)
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.address.cmp(&b.address));
+    write_entries(path, &entries)
+}
+
+fn write_entries(path: &Path, entries: &[&HostEntry]) -> std::io::Result<()> {
+    let content = match HostsFormat::from_path(path) {
+        HostsFormat::Text =>
+            entries
+                .iter()
+                .map(|entry| entry.to_line())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        HostsFormat::Json => serde_json::to_string_pretty(entries).map_err(std::io::Error::other)?,
+        HostsFormat::Yaml => serde_yaml::to_string(entries).map_err(std::io::Error::other)?,
+    };
+    write_atomic(path, &content)
+}
+
+// write a temporary file next to path and rename it over path, so a crash never leaves a half written file
+pub fn write_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    std::fs::write(&temp_path, content)?;
+    std::fs::rename(&temp_path, path)
+}
+
+// drop blank lines and later lines of an address that is already listed from a text hosts file,
+// returns the number of removed lines, invalid lines are kept for the user to fix
+pub fn clean_hosts_file(path: &Path) -> std::io::Result<usize> {
+    if HostsFormat::from_path(path) != HostsFormat::Text {
+        return Ok(0);
+    }
+    let content = std::fs::read_to_string(path)?;
+    let mut seen = HashSet::new();
+    let lines = content
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .filter(|line| {
+            let address = line.split_whitespace().next().unwrap_or_default();
+            seen.insert(address.to_string())
+        })
+        .collect::<Vec<_>>();
+    let removed = content.lines().count() - lines.len();
+    if removed > 0 {
+        write_atomic(path, &lines.join("\n"))?;
+    }
+    Ok(removed)
+}
+
+// build the hosts map from the host list, keeping saved state for hosts that are still listed
+pub fn reconcile(
+    entries: Vec<HostEntry>,
+    mut saved: HashMap<String, HostState>
+) -> HashMap<String, Host> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            let state = saved.remove(&entry.address).unwrap_or_default();
+            (entry.address.clone(), Host { entry, state })
+        })
+        .collect()
+}
+
+// option values can't contain whitespace, so special characters are written as %XX
+fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3).ok_or(format!("invalid escape in '{}'", value))?;
+            let byte = u8
+                ::from_str_radix(hex, 16)
+                .map_err(|_| format!("invalid escape %{} in '{}'", hex, value))?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|e| e.to_string())
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_graphic() && byte != b'%' {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_subnet_gets_the_same_checks_as_text() {
+        let content = r#"[
+            {"address": "10.0.0.0/28", "method": {"type": "subnet", "network": "10.0.0.0/28"}},
+            {"address": "10.0.0.0/16", "method": {"type": "subnet", "network": "10.0.0.0/16"}},
+            {"address": "10.0.0.5/24", "method": {"type": "subnet", "network": "10.0.0.5/24"}}
+        ]"#;
+        let parsed = parse_hosts(content, HostsFormat::Json).unwrap();
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.entries[0].address, "10.0.0.0/28");
+        assert_eq!(parsed.invalid.len(), 2);
+        assert!(HostEntry::parse("10.0.0.0/16").is_err());
+        assert!(HostEntry::parse("10.0.0.5/24").is_err());
+    }
+
+    #[test]
+    fn yaml_fallback_only_for_pinged_hosts() {
+        let content = "
+- address: tcp://10.0.0.5:22
+  fallback: nmap
+  method:
+    type: tcp
+    host: 10.0.0.5
+    port: 22
+- address: 10.0.0.6
+  fallback: nmap
+";
+        let parsed = parse_hosts(content, HostsFormat::Yaml).unwrap();
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.entries[0].address, "10.0.0.6");
+        assert_eq!(parsed.invalid[0].0, "tcp://10.0.0.5:22");
+        assert!(HostEntry::parse("tcp://10.0.0.5:22 fallback=nmap").is_err());
+    }
+
+    #[test]
+    fn option_like_hosts_are_rejected() {
+        assert!(HostEntry::parse("-oN /etc/passwd").is_err());
+        assert!(HostEntry::parse("tcp://-oN:22").is_err());
+        let content = r#"[
+            {"address": "-oN"},
+            {"address": "tcp://-oN:22", "method": {"type": "tcp", "host": "-oN", "port": 22}},
+            {"address": "10.0.0.5"}
+        ]"#;
+        let parsed = parse_hosts(content, HostsFormat::Json).unwrap();
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.invalid.len(), 2);
+        let content = "- address: -oN\n";
+        let parsed = parse_hosts(content, HostsFormat::Yaml).unwrap();
+        assert!(parsed.entries.is_empty());
+        assert_eq!(parsed.invalid.len(), 1);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use log::{ debug, error, info };
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::net::{ TcpListener, TcpStream };
+use tokio::time::timeout;
+
+// minimal http/1.1 server, one request per connection
+
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    // header names are lowercase
+    pub headers: HashMap<String, String>,
+    // read up to Content-Length, empty without one
+    pub body: String,
+}
+
+impl Request {
+    // token from an "Authorization: Bearer <token>" header
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.headers.get("authorization")?.strip_prefix("Bearer ").map(str::trim)
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: serde_json::Value) -> Self {
+        Response { status, content_type: "application/json", body: body.to_string() }
+    }
+
+    pub fn text(status: u16, body: &str) -> Self {
+        Response { status, content_type: "text/plain", body: body.to_string() }
+    }
+}
+
+// accept connections on bind forever, each request is answered by handler
+pub async fn serve<F, Fut>(bind: String, handler: F)
+    where F: Fn(Request) -> Fut + Clone + Send + 'static, Fut: Future<Output = Response> + Send
+{
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Unable to bind http server to {} => {}", bind, e);
+            return;
+        }
+    };
+    info!("HTTP server listening on {}", bind);
+
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("HTTP accept error => {}", e);
+                continue;
+            }
+        };
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let response = match timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+                Ok(Ok(request)) => {
+                    debug!("HTTP {} {} from {}", request.method, request.path, peer);
+                    handler(request).await
+                }
+                Ok(Err(e)) => Response::text(400, &e),
+                Err(_) => Response::text(408, "request timeout"),
+            };
+            if let Err(e) = write_response(&mut stream, response).await {
+                debug!("HTTP write error to {} => {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<Request, String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEAD_SIZE {
+            return Err("request head too large".to_string());
+        }
+        let n = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("connection closed".to_string());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().ok_or("missing method")?.to_string();
+    let path = request_line.next().ok_or("missing path")?.to_string();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect::<HashMap<_, _>>();
+
+    let content_length = match headers.get("content-length") {
+        Some(value) => value.parse::<usize>().map_err(|_| "invalid content-length")?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_SIZE {
+        return Err("request body too large".to_string());
+    }
+    // part of the body may have arrived with the head
+    let mut body = buf.split_off(head_end + 4);
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("connection closed".to_string());
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+    let body = String::from_utf8(body).map_err(|_| "request body is not utf-8")?;
+
+    Ok(Request { method, path, headers, body })
+}
+
+async fn write_response(stream: &mut TcpStream, response: Response) -> std::io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    let payload = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    stream.write_all(payload.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -1,24 +1,175 @@
 use std::collections::{ HashMap, HashSet };
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{ Path, PathBuf };
 use std::process::{ exit };
 use std::sync::Arc;
 use std::time::{ Duration, Instant };
-use std::fs::{ read_to_string, OpenOptions };
 use dotenv::dotenv;
-use log::{ debug, error, info };
-use teloxide::dispatching::dialogue::{ InMemStorage, Dialogue };
+use log::{ debug, error, info, warn };
+use teloxide::dispatching::dialogue::{ Dialogue, ErasedStorage, InMemStorage, Storage };
 use tokio::fs;
-use tokio::sync::{ Mutex, oneshot };
-use tokio::process::Command;
-use teloxide::{ prelude::*, types::ChatId, RequestError, Bot };
+use tokio::sync::{ Mutex, mpsc, oneshot };
+use teloxide::{ prelude::*, types::ChatId, ApiError, RequestError, Bot };
+use teloxide::net::Download;
+use teloxide::types::{ Document, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, Me, ParseMode };
+use teloxide::utils::command::{ BotCommands, ParseError };
 use tokio::time::{ sleep };
+use rand::Rng;
+use toml_edit::DocumentMut;
 use serde::{ Serialize, Deserialize };
+use host_state::{ HostState, LatencyChange };
+use hosts::{ CheckMethod, Host, HostEntry, HostsFormat };
+use checks::{ CheckOutcome, CheckStatus };
+use maintenance::MaintenanceWindow;
+use messages::{
+    ChatPreferences,
+    Notification,
+    Severity,
+    SeverityChats,
+    SeverityPrefixes,
+    Templates,
+    Verbosity,
+    escape_md_v2,
+};
+
+mod api;
+mod checks;
+mod discord;
+mod email;
+mod health;
+mod dialogue_storage;
+mod host_state;
+mod hosts;
+mod http;
+mod maintenance;
+mod messages;
+mod neighbors;
+mod nmap;
+mod resources;
+mod runner;
+mod selftest;
+mod stats;
+mod webhook;
+
+const CONFIG_PATH: &str = "config.toml";
+// telegram rejects messages longer than this
+const MAX_MESSAGE_LENGTH: usize = 4096;
+// telegram limits inline button callback data to 64 bytes
+const MAX_CALLBACK_DATA_LENGTH: usize = 64;
+const REMOVE_CALLBACK_PREFIX: &str = "remove:";
+const ADD_CALLBACK_PREFIX: &str = "add:";
+// buttons offered by /addfrom, the rest can be added with "Add all" or /add
+const ADDFROM_MAX_BUTTONS: usize = 40;
+// lower bound for ping_interval, shorter intervals make the monitoring loop hammer the hosts
+const MIN_PING_INTERVAL: u64 = 5;
+// upper bound for ping_interval, an outage must not go unnoticed for more than a day
+const MAX_PING_INTERVAL: u64 = 24 * 60 * 60;
+// ping_interval values offered as buttons by /config
+const CONFIG_INTERVAL_PRESETS: [u64; 3] = [30, 60, 300];
+// the watchdog alerts when no check cycle finished for this many ping intervals
+const WATCHDOG_STALL_FACTOR: u64 = 3;
+// but not before this, so short intervals with slow checks don't alert
+const WATCHDOG_MIN_STALL: Duration = Duration::from_secs(60);
+// largest hosts file accepted by /import
+const MAX_IMPORT_SIZE: u32 = 1024 * 1024;
+// fake host used by /test, .invalid and TEST-NET-1 are reserved and never resolve
+const TEST_HOST: &str = "test-host.invalid";
+const TEST_HOST_IP: &str = "192.0.2.1";
+// hosts listed by /top without a count
+const TOP_DEFAULT_COUNT: usize = 5;
+// /invite tokens expire after this unless a duration is given
+const INVITE_DEFAULT_VALIDITY: Duration = Duration::from_secs(24 * 60 * 60);
+const INVITE_TOKEN_LENGTH: usize = 24;
+// first backoff between notification send attempts, doubled on every retry
+const SEND_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 struct BotConfig {
     ping_interval: u64,
     ping_args: Vec<String>,
+    maintenance_windows: Vec<MaintenanceWindow>,
+    // daily window in which only critical notifications (outages) are sent, set with /quiethours
+    quiet_hours: Option<maintenance::Schedule>,
+    // minutes between reminders for hosts that stay offline, 0 disables reminders
+    reminder_interval: u64,
+    // hosts answering slower than this are reported as degraded, 0 disables
+    latency_threshold_ms: f64,
+    // online hosts losing more than this percentage of ping packets are reported as degraded, 0 disables
+    loss_threshold: f64,
+    templates: Templates,
+    severity_prefixes: SeverityPrefixes,
+    // address for the http api eg. "0.0.0.0:8080", the api is disabled when unset
+    http_bind: Option<String>,
+    // bearer token required by the http api
+    api_token: Option<String>,
+    // address for the unauthenticated /healthz liveness endpoint eg. "0.0.0.0:8081", disabled when unset
+    health_bind: Option<String>,
+    // chat or group that receives notifications, the chat that ran /start when unset
+    alert_chat_id: Option<i64>,
+    // notifications of a severity listed here go to these chats instead of the alert chat
+    severity_chats: SeverityChats,
+    // alerts when the bot's own machine runs out of cpu, memory or disk
+    resources: resources::ResourceThresholds,
+    // ca bundles and client certificates for http checks, referenced by name with tls= in the hosts file
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    tls_profiles: HashMap<String, checks::TlsProfile>,
+    // chats with full access from startup that are never asked for the password
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    preauthorized_chats: Vec<i64>,
+    // notifications are also posted to this discord webhook when set
+    discord_webhook_url: Option<String>,
+    // endpoints notified of every host event with a payload shaped by their template
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    webhooks: Vec<webhook::WebhookConfig>,
+    // tokens of extra telegram bots that also deliver every notification, in case the main bot is revoked
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    backup_bot_tokens: Vec<String>,
+    // notification preferences by chat id, set with /prefs, chats without an entry get everything
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    chat_preferences: HashMap<String, ChatPreferences>,
+    // email notifications, disabled when the [email] section is missing
+    email: Option<email::EmailConfig>,
+    // extra attempts for notifications that fail with a transient telegram or network error
+    send_retries: u32,
+    // start monitoring for last_chat_id on startup instead of waiting for /start
+    autostart: bool,
+    // seconds a chat has to wait between /status or /scanports runs, 0 disables
+    scan_cooldown: u64,
+    // nmap output lines shown per host by /status, 0 shows everything
+    status_max_lines: usize,
+    // up to this many seconds of random delay before the first check, 0 disables
+    startup_jitter: u64,
+    // up to this many seconds of random delay added to every wait between checks, 0 disables
+    cycle_jitter: u64,
+    // seconds after monitoring starts in which state is tracked but nothing is notified, 0 disables
+    startup_grace: u64,
+    // seconds in which a notification for the same host and event is sent only once, 0 disables
+    dedup_window: u64,
+    // /add and /import refuse hosts beyond this many
+    max_hosts: usize,
+    // "nmap" checks plain hosts in the monitoring loop with an nmap scan instead of ping
+    monitor_method: checks::MonitorMethod,
+    // "nmap" or "tcp:<port>" retries plain hosts that failed their check before they count as offline
+    fallback: checks::Fallback,
+    // "sudo" or "capabilities" run /status as a syn scan instead of a connect scan
+    scan_privileges: nmap::ScanPrivileges,
+    // scan recovered hosts and report ports that were open before the outage but are closed now
+    recovery_port_check: bool,
+    // limits of the per host history and scan statistics kept in memory and state.json
+    retention: host_state::Retention,
+    // hosts changing state more than this many times within flap_window only get a flapping alert, 0 disables
+    flap_threshold: usize,
+    // seconds, a flapping host is stable again after a whole window without a change
+    flap_window: u64,
+    // chat that last ran /start, kept up to date by the bot
+    last_chat_id: Option<i64>,
+    // hosts file, the extension picks the format (.txt, .json, .yaml), set by /migrate
+    hosts_path: Option<PathBuf>,
+    // IANA name eg. "Europe/Helsinki" for timestamps in messages, UTC when unset
+    timezone: Option<chrono_tz::Tz>,
+    // keep pending prompts like /add in dialogues.json next to the hosts file across restarts
+    persist_dialogues: bool,
 }
 impl Default for BotConfig {
     fn default() -> Self {
@@ -32,24 +183,130 @@ impl Default for BotConfig {
                 "-W".to_string(),
                 "0.5".to_string()
             ],
+            maintenance_windows: Vec::new(),
+            quiet_hours: None,
+            reminder_interval: 0,
+            latency_threshold_ms: 0.0,
+            loss_threshold: 0.0,
+            templates: Templates::default(),
+            severity_prefixes: SeverityPrefixes::default(),
+            http_bind: None,
+            health_bind: None,
+            api_token: None,
+            alert_chat_id: None,
+            severity_chats: SeverityChats::default(),
+            preauthorized_chats: Vec::new(),
+            tls_profiles: HashMap::new(),
+            resources: resources::ResourceThresholds::default(),
+            discord_webhook_url: None,
+            webhooks: Vec::new(),
+            email: None,
+            send_retries: 3,
+            autostart: false,
+            scan_cooldown: 30,
+            status_max_lines: 25,
+            scan_privileges: nmap::ScanPrivileges::None,
+            monitor_method: checks::MonitorMethod::Ping,
+            fallback: checks::Fallback::None,
+            recovery_port_check: false,
+            retention: host_state::Retention::default(),
+            flap_threshold: 0,
+            flap_window: 600,
+            max_hosts: 1000,
+            startup_jitter: 0,
+            startup_grace: 0,
+            dedup_window: 0,
+            cycle_jitter: 0,
+            last_chat_id: None,
+            hosts_path: None,
+            persist_dialogues: false,
+            timezone: None,
+            backup_bot_tokens: Vec::new(),
+            chat_preferences: HashMap::new(),
+        }
+    }
+}
+
+impl BotConfig {
+    // chats a notification goes to, chat_id is the chat that started monitoring
+    fn notification_chats(&self, chat_id: ChatId, severity: Severity) -> Vec<ChatId> {
+        match self.severity_chats.chats(severity) {
+            [] => vec![self.alert_chat_id.map(ChatId).unwrap_or(chat_id)],
+            chats => chats.iter().copied().map(ChatId).collect(),
         }
     }
 }
 
 #[derive(Default)]
 struct AppState {
-    allowed_chats: Vec<ChatId>,
+    allowed_chats: HashMap<ChatId, Role>,
     hosts_path: PathBuf,
-    hosts: HashMap<String, bool>,
+    state_path: PathBuf,
+    hosts: HashMap<String, Host>,
     password: String,
+    // optional second password granting read only access
+    viewer_password: Option<String>,
+    // last run of expensive commands per chat, for scan_cooldown
+    last_scans: HashMap<(ChatId, &'static str), Instant>,
+    // recent /status scan durations for /stats
+    scan_stats: stats::ScanStats,
+    // result of the last /status or /diff scan by address, compared by /diff
+    last_scan: Option<HashMap<String, nmap::HostScanResult>>,
+    // one time tokens from /invite accepted instead of a password, with the role they grant and expiry
+    invites: HashMap<String, (Role, Instant)>,
+    // runs ping and nmap, a mock with the mock-commands feature
+    runner: runner::Runner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    // can only view status, hosts and config
+    Viewer,
+    // full access to monitoring, hosts and config
+    Admin,
 }
 #[derive(Default, Debug)]
 struct BotState {
     task: Option<oneshot::Sender<()>>,
+    // checks are skipped while paused, host state and timers are kept
+    paused: bool,
+    // all notifications are dropped while silenced, checks keep running and host state is tracked
+    silenced: bool,
+    // temporary ping interval set by /fastcheck and when it ends
+    fast_check: Option<(u64, Instant)>,
+    // when the monitoring loop last finished a cycle, checked by the watchdog
+    last_cycle: Option<Instant>,
+    // wakes the monitoring loop so a new interval applies to the current wait
+    interval_changed: Arc<tokio::sync::Notify>,
+    // makes the monitoring loop check every host right away, sent by /check
+    check_now: Arc<tokio::sync::Notify>,
     chat_id: Option<ChatId>,
     config: BotConfig,
 }
 
+impl BotState {
+    fn fast_check_interval(&self) -> Option<u64> {
+        match self.fast_check {
+            Some((interval, until)) if Instant::now() < until => Some(interval),
+            _ => None,
+        }
+    }
+
+    // seconds between checks, the /fastcheck interval while it lasts
+    fn ping_interval(&self) -> u64 {
+        self.fast_check_interval().unwrap_or(self.config.ping_interval)
+    }
+
+    // seconds between checks of a host with its own interval, /fastcheck can only shorten it
+    fn host_interval(&self, interval: Option<u64>) -> u64 {
+        match (interval, self.fast_check_interval()) {
+            (Some(interval), Some(fast)) => interval.min(fast),
+            (Some(interval), None) => interval,
+            (None, _) => self.ping_interval(),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Default)]
 enum DialogueState {
     #[default]
@@ -57,13 +314,177 @@ enum DialogueState {
     WaitingForPassword,
     WaitingForHostAdd,
     WaitingForHostRemove,
+    // host picked for /remove, only "yes" removes it
+    ConfirmHostRemove {
+        host: String,
+    },
+    // waiting for a hosts file document, replace drops hosts missing from the file
+    WaitingForImport {
+        replace: bool,
+    },
+    // /config reset was sent, only "yes" restores the defaults
+    ConfirmConfigReset,
+}
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase", description = "Commands:")]
+enum BotCommand {
+    #[command(description = "begin monitoring hosts and sending notifications")]
+    Start,
+    #[command(description = "stop monitoring")]
+    Stop,
+    #[command(description = "skip checks until /resume, keeping host state")]
+    Pause,
+    #[command(description = "resume checks after /pause")]
+    Resume,
+    #[command(description = "drop every notification, checks keep running (on|off)")]
+    Silence(String),
+    #[command(description = "only send outages during a daily window ([HH:MM-HH:MM[@days]|off])")]
+    Quiethours(String),
+    #[command(description = "check all hosts right away")]
+    Check,
+    #[command(description = "clear outages, acknowledgements and flapping of all hosts and check again")]
+    Reset,
+    #[command(description = "check more often for a while (<seconds> <duration>|off)")]
+    Fastcheck(String),
+    #[command(description = "change the ping interval, same as /config edit ping_interval (<seconds>)")]
+    Setinterval(String),
+    #[command(description = "send notifications to this or another chat ([chat id|off])")]
+    Setalertchat(String),
+    #[command(description = "create a one time invite token ([viewer] [duration])")]
+    Invite(String),
+    #[command(description = "nmap scan of all hosts ([--down|--up] [--sort name|latency])")]
+    Status(String),
+    #[command(description = "scan all hosts and report changes since the previous scan")]
+    Diff,
+    #[command(description = "choose which notifications this chat gets (severity|verbosity|reset)")]
+    Prefs(String),
+    #[command(description = "add a host")]
+    Add,
+    #[command(description = "remove a host")]
+    Remove,
+    #[command(description = "offer hosts from the system neighbor table for adding (arp)")]
+    Addfrom(String),
+    #[command(description = "show or edit the configuration ([list|edit|validate|reset])")]
+    Config(String),
+    #[command(description = "list all hosts with their state")]
+    Hosts,
+    #[command(description = "list offline hosts")]
+    Down,
+    #[command(description = "list online hosts")]
+    Up,
+    #[command(description = "list hosts not checked since start")]
+    Pending,
+    #[command(description = "hosts with the most outages ([count] [downtime])")]
+    Top(String),
+    #[command(description = "find hosts by address or label (<text>)")]
+    Find(String),
+    #[command(description = "set or remove the label of a host (<host> [name])")]
+    Label(String),
+    #[command(description = "set or remove the note of a host (<host> [text])")]
+    Note(String),
+    #[command(description = "monitor every address of a cidr range as one host (<cidr> [label])")]
+    Subnet(String),
+    #[command(description = "let alerts of a host through /silence and quiet hours (<host> on|off)")]
+    Critical(String),
+    #[command(description = "rewrite the hosts file in another format ([json|yaml|text])")]
+    Migrate(String),
+    #[command(description = "send the hosts file and config as documents")]
+    Export,
+    #[command(description = "add or replace hosts from a file ([replace])")]
+    Import(String),
+    #[command(description = "acknowledge an ongoing outage (<host>)")]
+    Ack(String),
+    #[command(description = "mute the notifications of one host for a while (<host> <duration>|off)")]
+    Snooze(String),
+    #[command(description = "show ping latency ([host])")]
+    Latency(String),
+    #[command(description = "when a host went offline and came back (<host>)")]
+    History(String),
+    #[command(description = "nmap service scan of a host (<host>)")]
+    Scanports(String),
+    #[command(description = "abort a pending prompt")]
+    Cancel,
+    #[command(description = "duration of recent scans")]
+    Stats,
+    #[command(description = "check that ping and nmap work")]
+    Selftest,
+    #[command(description = "show the commands run for a host without running them (<host>)")]
+    Debug(String),
+    #[command(description = "send a simulated offline notification")]
+    Test,
+    #[command(description = "show this list")]
+    Help,
+}
+
+impl BotCommand {
+    // commands that change monitoring, hosts or config and need the admin role
+    fn requires_admin(&self) -> bool {
+        match self {
+            // /config without arguments, /config list and /config validate only show the config
+            BotCommand::Config(args) =>
+                args
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|subcommand| {
+                        !subcommand.eq_ignore_ascii_case("list") && !subcommand.eq_ignore_ascii_case("validate")
+                    }),
+            | BotCommand::Start
+            | BotCommand::Stop
+            | BotCommand::Pause
+            | BotCommand::Resume
+            | BotCommand::Silence(_)
+            | BotCommand::Quiethours(_)
+            | BotCommand::Check
+            | BotCommand::Reset
+            | BotCommand::Fastcheck(_)
+            | BotCommand::Setinterval(_)
+            | BotCommand::Setalertchat(_)
+            | BotCommand::Invite(_)
+            | BotCommand::Prefs(_)
+            | BotCommand::Add
+            | BotCommand::Addfrom(_)
+            | BotCommand::Remove
+            | BotCommand::Label(_)
+            | BotCommand::Note(_)
+            | BotCommand::Critical(_)
+            | BotCommand::Subnet(_)
+            | BotCommand::Migrate(_)
+            | BotCommand::Export
+            | BotCommand::Import(_)
+            | BotCommand::Ack(_)
+            | BotCommand::Snooze(_)
+            | BotCommand::Scanports(_) => true,
+            | BotCommand::Status(_)
+            | BotCommand::Diff
+            | BotCommand::Hosts
+            | BotCommand::Down
+            | BotCommand::Up
+            | BotCommand::Pending
+            | BotCommand::Top(_)
+            | BotCommand::Find(_)
+            | BotCommand::Latency(_)
+            | BotCommand::History(_)
+            | BotCommand::Cancel
+            | BotCommand::Stats
+            | BotCommand::Selftest
+            | BotCommand::Debug(_)
+            | BotCommand::Test
+            | BotCommand::Help => false,
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    pretty_env_logger::init();
-
     dotenv().ok();
+
+    // LOG_FORMAT=json emits one json object per line for log pipelines
+    if std::env::var("LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json")) {
+        init_json_logger();
+    } else {
+        pretty_env_logger::init();
+    }
     let mut hosts_path = PathBuf::new();
 
     if cfg!(not(debug_assertions)) {
@@ -72,17 +493,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         hosts_path.push("hosts.txt");
     }
 
-    let bot = Bot::from_env();
+    // host state is kept next to the hosts file
+    let state_path = hosts_path.with_file_name("state.json");
+
+    // TELEGRAM_PROXY eg. "socks5://127.0.0.1:1080" or "http://proxy:3128" for hosts that can't reach telegram directly
+    let bot = match std::env::var("TELEGRAM_PROXY") {
+        Ok(proxy) if !proxy.is_empty() => {
+            let client = reqwest::Proxy
+                ::all(&proxy)
+                .and_then(|proxy| teloxide::net::default_reqwest_settings().proxy(proxy).build());
+            match client {
+                Ok(client) => {
+                    // the url may contain credentials, don't log it
+                    info!("Connecting to Telegram through TELEGRAM_PROXY");
+                    Bot::from_env_with_client(client)
+                }
+                Err(e) => {
+                    error!("Invalid TELEGRAM_PROXY => {}", e);
+                    exit(1);
+                }
+            }
+        }
+        _ => Bot::from_env(),
+    };
     let bot_state = Arc::new(Mutex::new(BotState::default()));
     let app_state = Arc::new(
         Mutex::new(AppState {
-            password: std::env::var("BOT_PASSWORD").unwrap_or("default_password".to_string()),
-            hosts_path: hosts_path,
+            password: secret_from_env("BOT_PASSWORD").unwrap_or("default_password".to_string()),
+            viewer_password: secret_from_env("VIEWER_PASSWORD"),
+            hosts_path,
+            state_path,
             ..Default::default()
         })
     );
+    // MOCK_COMMANDS answers ping and nmap from a file, for testing without a network
+    #[cfg(feature = "mock-commands")]
+    if let Ok(path) = std::env::var("MOCK_COMMANDS") {
+        warn!("Commands are answered from {} instead of being run", path);
+        app_state.lock().await.runner = runner::Runner::new(runner::MockRunner::new(path));
+    }
     // read and load config
-    let bot_config_path = "config.toml";
+    let bot_config_path = CONFIG_PATH;
     let result = match fs::read_to_string(&bot_config_path).await {
         Ok(r) => r,
         Err(_) => {
@@ -90,9 +541,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             exit(1);
         }
     };
-    match toml::from_str(&result) {
-        Ok(result) => {
+    match validate_config(&result) {
+        Ok((_, issues)) if !issues.errors.is_empty() => {
+            for e in &issues.errors {
+                error!("Invalid configuration in {} => {}", bot_config_path, e);
+            }
+            exit(1);
+        }
+        Ok((result, issues)) => {
+            for warning in &issues.warnings {
+                warn!("{} => {}", bot_config_path, warning);
+            }
             let mut bot_state_guard = bot_state.lock().await;
+            if let Some(hosts_path) = &result.hosts_path {
+                let mut app_state_guard = app_state.lock().await;
+                app_state_guard.hosts_path = hosts_path.clone();
+                app_state_guard.state_path = hosts_path.with_file_name("state.json");
+            }
+            messages::set_timezone(result.timezone.unwrap_or(chrono_tz::Tz::UTC));
+            health::set_config_ok(true);
             bot_state_guard.config = result;
         }
         Err(e) => {
@@ -105,467 +572,3518 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let bot_state_clone = Arc::clone(&bot_state);
     let app_state_clone = Arc::clone(&app_state);
 
-    let dialogue_storage = InMemStorage::<DialogueState>::new();
+    let dialogue_storage = if bot_state.lock().await.config.persist_dialogues {
+        let path = app_state.lock().await.state_path.with_file_name("dialogues.json");
+        dialogue_storage::JsonFileStorage::<DialogueState>::open(path).erase()
+    } else {
+        InMemStorage::<DialogueState>::new().erase()
+    };
 
     let mut app_state_guard = app_state.lock().await;
-    app_state_guard.hosts = read_to_string(app_state_guard.hosts_path.clone())
-        .unwrap()
-        .lines()
-        .map(String::from)
-        .collect::<HashSet<_>>()
-        .into_iter()
-        .map(|host| (host, true))
-        .collect();
+    // files written by older versions can hold blank and duplicate lines
+    match hosts::clean_hosts_file(&app_state_guard.hosts_path) {
+        Ok(0) => {}
+        Ok(removed) => warn!("Removed {} blank or duplicate lines from {}", removed, app_state_guard.hosts_path.display()),
+        Err(e) => error!("Unable to clean up {} => {}", app_state_guard.hosts_path.display(), e),
+    }
+    let hosts = match hosts::load_hosts(&app_state_guard.hosts_path) {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            error!("Unable to load hosts from {} => {}", app_state_guard.hosts_path.display(), e);
+            exit(1);
+        }
+    };
+    let max_hosts = bot_state.lock().await.config.max_hosts;
+    if hosts.len() > max_hosts {
+        // hosts are kept, only /add and /import refuse new ones
+        warn!("{} lists {} hosts, more than max_hosts {}", app_state_guard.hosts_path.display(), hosts.len(), max_hosts);
+    }
+    let saved_state = host_state::load_state(&app_state_guard.state_path);
+    app_state_guard.hosts = hosts::reconcile(hosts, saved_state);
+    // a state file from before the retention limits were lowered is cut down right away
+    let retention = bot_state.lock().await.config.retention.clone();
+    for host in app_state_guard.hosts.values_mut() {
+        host.state.prune(&retention);
+    }
+    host_state::save_state(&app_state_guard.state_path, &app_state_guard.hosts);
     info!("HOSTS -> {:?}", app_state_guard.hosts);
+    for chat_id in bot_state.lock().await.config.preauthorized_chats.iter() {
+        app_state_guard.allowed_chats.insert(ChatId(*chat_id), Role::Admin);
+        info!("Chat ID {} is preauthorized", chat_id);
+    }
     drop(app_state_guard);
 
-    let handler = Update::filter_message()
-        .enter_dialogue::<Message, InMemStorage<DialogueState>, DialogueState>()
-        .endpoint(dialogue_handler);
+    let (http_bind, api_token) = {
+        let bot_state_guard = bot_state.lock().await;
+        (bot_state_guard.config.http_bind.clone(), bot_state_guard.config.api_token.clone())
+    };
+    if let Some(bind) = http_bind {
+        let api = api::Api {
+            bot: bot.clone(),
+            bot_state: Arc::clone(&bot_state),
+            app_state: Arc::clone(&app_state),
+            token: api_token,
+        };
+        tokio::spawn(http::serve(bind, move |request| api::handle(request, api.clone())));
+    }
+    // separate from the api so probes need no token and keep working without http_bind
+    if let Some(bind) = bot_state.lock().await.config.health_bind.clone() {
+        tokio::spawn(http::serve(bind, health::handle));
+    }
+
+    // surface a missing nmap or ping without permissions before the first check fails
+    let ping_args = bot_state.lock().await.config.ping_args.clone();
+    let runner = app_state.lock().await.runner.clone();
+    selftest::log(&selftest::run(&runner, &ping_args).await);
+
+    let (autostart, last_chat_id) = {
+        let bot_state_guard = bot_state.lock().await;
+        (bot_state_guard.config.autostart, bot_state_guard.config.last_chat_id)
+    };
+    if autostart {
+        match last_chat_id {
+            Some(chat_id) => {
+                start_monitoring(&bot, &bot_state, &app_state, ChatId(chat_id)).await;
+            }
+            None => info!("Autostart is enabled but no chat has run /start yet"),
+        }
+    }
+
+    let handler = dptree
+        ::entry()
+        .branch(
+            Update::filter_message()
+                .enter_dialogue::<Message, ErasedStorage<DialogueState>, DialogueState>()
+                .branch(
+                    dptree::case![DialogueState::Default].filter_command::<BotCommand>().endpoint(command_handler)
+                )
+                .branch(dptree::endpoint(dialogue_handler))
+        )
+        .branch(Update::filter_callback_query().endpoint(callback_handler));
 
+    health::set_dispatcher_running(true);
     Dispatcher::builder(bot, handler)
         .dependencies(dptree::deps![bot_state_clone, app_state_clone, dialogue_storage])
-        .default_handler(|_| async move { () })
+        .default_handler(|_| async move {})
         .build()
         .dispatch().await;
+    health::set_dispatcher_running(false);
 
     Ok(())
 }
 
-async fn dialogue_handler(
+// value of NAME_FILE's file if set, eg. a docker secret mount, otherwise of NAME
+fn secret_from_env(name: &str) -> Option<String> {
+    let file_var = format!("{}_FILE", name);
+    let Ok(path) = std::env::var(&file_var) else {
+        return std::env::var(name).ok();
+    };
+    match std::fs::read_to_string(&path) {
+        // editors and `echo` leave a trailing newline
+        Ok(content) => Some(content.trim_end_matches(['\r', '\n']).to_string()),
+        Err(e) => {
+            // falling back would silently accept a weaker password
+            error!("Unable to read {} from {} => {}", file_var, path, e);
+            exit(1);
+        }
+    }
+}
+
+fn init_json_logger() {
+    env_logger::Builder
+        ::from_default_env()
+        .format(|buf, record| {
+            let line =
+                serde_json::json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        })
+        .init();
+}
+
+// handles commands in the default dialogue state, other messages go to dialogue_handler
+async fn command_handler(
     bot: Bot,
     msg: Message,
-    dialogue: Dialogue<DialogueState, InMemStorage<DialogueState>>,
+    cmd: BotCommand,
+    dialogue: Dialogue<DialogueState, ErasedStorage<DialogueState>>,
     bot_state: Arc<Mutex<BotState>>,
     app_state: Arc<Mutex<AppState>>
 ) -> Result<(), RequestError> {
     let chat_id = msg.chat.id;
-    let text = msg.text().unwrap_or("");
-    let state = match dialogue.get().await {
-        Ok(state) => state.unwrap_or(DialogueState::Default),
-        Err(e) => {
-            info!("Dialogue error: {}", e);
-            DialogueState::Default
-        }
+    let Some(role) = chat_role(&bot, chat_id, &dialogue, &app_state).await? else {
+        return Ok(());
     };
 
-    match state {
-        DialogueState::Default => {
-            let allowed_chats = {
-                let app_state_guard = app_state.lock().await;
-                app_state_guard.allowed_chats.clone()
-            };
+    if role != Role::Admin && cmd.requires_admin() {
+        bot.send_message(chat_id, "This command requires full access.").await?;
+        return Ok(());
+    }
 
-            if !allowed_chats.contains(&chat_id) {
-                bot.send_message(chat_id, "Enter password").await?;
-                if let Err(e) = dialogue.update(DialogueState::WaitingForPassword).await {
-                    info!("Dialogue update error: {}", e);
+    match &cmd {
+        BotCommand::Status(args) => {
+            let filter = match StatusFilter::parse(args) {
+                Ok(filter) => filter,
+                Err(e) => {
+                    bot.send_message(chat_id, format!("{}. Usage: /status [--down|--up] [--sort name|latency]", e)).await?;
+                    return Ok(());
                 }
+            };
+            if let Some(wait) = scan_cooldown(&bot_state, &app_state, chat_id, "/status").await {
+                bot.send_message(chat_id, format!("Please wait {}s before scanning again", wait)).await?;
                 return Ok(());
             }
+            let hosts = {
+                let app_state_guard = app_state.lock().await;
+                app_state_guard.hosts.clone()
+            };
+            let (responses, scan_time) = scan_hosts(&bot_state, &app_state, &hosts).await;
+            let max_lines = bot_state.lock().await.config.status_max_lines;
+            let results = filter.apply(
+                responses
+                    .into_iter()
+                    .map(|(ip, output)| (ip, nmap::outcome(&output)))
+                    .collect()
+            );
 
-            if text.starts_with("/status") {
-                let mut handles = Vec::new();
-                let hosts = {
-                    let app_state_guard = app_state.lock().await;
-                    app_state_guard.hosts.clone()
-                };
-                // start timer for host scan
-                let scan_start = Instant::now();
-
-                for (ip, _) in hosts {
-                    let handle = tokio::spawn(async move {
-                        let output = Command::new("/bin/nmap")
-                            .args(["-T3", "-sT", "-Pn", "--host-timeout", "10", ip.as_str()])
-                            .output().await;
-                        match output {
-                            Ok(output) => {
-                                let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                                if output.status.success() {
-                                    (true, format!("Host {}: {}", ip, stdout))
-                                } else {
-                                    let stderr = String::from_utf8_lossy(&output.stderr);
-                                    (false, format!("Host {} failed: {}", ip, stderr))
-                                }
-                            }
-                            Err(e) =>
-                                (false, format!("PING FAILED TO HOST -> {}, error -> {}", ip, e)),
-                        }
-                    });
-                    handles.push(handle);
-                }
-
-                let mut responses: Vec<String> = Vec::new();
-                for handle in handles {
-                    match handle.await {
-                        Ok(result) => {
-                            // remove empty lines from each result
-                            let result = result.1
-                                .lines()
-                                .filter(|line| !line.trim().is_empty())
-                                .collect::<Vec<&str>>()
-                                .join("\n");
-                            responses.push(result);
-                        }
-                        Err(e) => info!("ERROR -> {}", e),
-                    }
-                }
-                let scan_time = scan_start.elapsed().as_secs_f64();
+            // hosts that are down have near identical output, several of them share one line
+            let (down, up): (Vec<_>, Vec<_>) = results
+                .iter()
+                .partition(|(_, outcome)| outcome.status == CheckStatus::Down);
+            let detailed = if down.len() > 1 { up } else { results.iter().collect() };
 
-                // combine results to one string and remove unneccesary text
-                let mut combined_string = responses
+            // one block per host, host in bold and nmap output in a code block
+            let mut blocks = detailed
+                .iter()
+                .map(|(ip, CheckOutcome { output, .. })| {
+                    let note = hosts
+                        .get(ip)
+                        .and_then(|host| host.entry.note.as_ref())
+                        .map(|note| format!("\n_{}_", escape_md_v2(&format!("Note: {}", note))))
+                        .unwrap_or_default();
+                    messages::code_block(
+                        &format!("*{}*{}", escape_md_v2(ip), note),
+                        &nmap::truncate_lines(output, max_lines)
+                    )
+                })
+                .collect::<Vec<_>>();
+            if down.len() > 1 {
+                let names = down
                     .iter()
-                    .map(|output| {
-                        // split output into lines, skip the first line, and join with newlines
-                        output.lines().skip(1).collect::<Vec<_>>().join("\n") + "\n\n" // add newlines to separate results
-                    })
-                    .collect::<String>();
-                info!("{}", combined_string);
+                    .map(|(ip, _)| ip.as_str())
+                    .collect::<Vec<_>>();
+                blocks.push(
+                    format!("*{}*", escape_md_v2(&format!("{} hosts down: {}", down.len(), names.join(", "))))
+                );
+            }
+            if results.is_empty() {
+                blocks.push(escape_md_v2("No hosts match."));
+            }
+            info!("{:?}", results);
 
-                combined_string += format!(
-                    "Nmap scan finnished in {scan_time:.2} seconds"
-                ).as_str();
+            let outages = outages_summary(&hosts);
+            if !outages.is_empty() {
+                blocks.push(escape_md_v2(outages.trim_end()));
+            }
+            let pending = pending_hosts(&hosts);
+            if !pending.is_empty() {
+                blocks.push(
+                    escape_md_v2(&format!("Not checked by monitoring yet: {}", pending.join(", ")))
+                );
+            }
+            let snoozed = snoozed_hosts(&hosts);
+            if !snoozed.is_empty() {
+                blocks.push(escape_md_v2(&format!("Snoozed: {}", snoozed.join(", "))));
+            }
+            blocks.push(
+                format!("_{}_", escape_md_v2(&format!("Nmap scan finnished in {scan_time:.2} seconds")))
+            );
 
-                bot.send_message(chat_id, &combined_string).await?;
-            } else if
-                // /start command
-                text.starts_with("/start")
-            {
-                let mut bot_state_guard = bot_state.lock().await;
+            send_chunked_md(&bot, chat_id, &blocks).await?;
+        }
+        BotCommand::Diff => {
+            if let Some(wait) = scan_cooldown(&bot_state, &app_state, chat_id, "/diff").await {
+                bot.send_message(chat_id, format!("Please wait {}s before scanning again", wait)).await?;
+                return Ok(());
+            }
+            let (hosts, previous) = {
+                let app_state_guard = app_state.lock().await;
+                (app_state_guard.hosts.clone(), app_state_guard.last_scan.clone())
+            };
+            // scan_hosts replaces last_scan with this scan
+            let (responses, _) = scan_hosts(&bot_state, &app_state, &hosts).await;
+            let Some(previous) = previous else {
+                bot.send_message(chat_id, "No previous scan to compare with, scanned now for the next /diff.").await?;
+                return Ok(());
+            };
+            send_chunked(&bot, chat_id, &scan_diff(&previous, &responses)).await?;
+        }
+        BotCommand::Start => {
+            if !start_monitoring(&bot, &bot_state, &app_state, chat_id).await {
+                bot.send_message(chat_id, "Task is already running!").await?;
+                return Ok(());
+            }
 
-                if bot_state_guard.task.is_some() {
-                    bot.send_message(chat_id, "Task is already running!").await?;
-                    return Ok(());
+            bot.send_message(
+                chat_id,
+                format!("Notification Bot started. Your chat ID is: {}", chat_id)
+            ).await?;
+        }
+        BotCommand::Stop => {
+            let reply = match stop_monitoring(&bot_state).await {
+                Ok(()) => "Task stopped.",
+                Err(reason) => reason,
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Pause => {
+            let mut bot_state_guard = bot_state.lock().await;
+            let reply = if bot_state_guard.task.is_none() {
+                "No task is running."
+            } else if bot_state_guard.paused {
+                "Monitoring is already paused."
+            } else {
+                bot_state_guard.paused = true;
+                info!("Monitoring paused for Chat ID: {}", chat_id);
+                "Monitoring paused, host state is kept. Use /resume to continue."
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Resume => {
+            let mut bot_state_guard = bot_state.lock().await;
+            let reply = if !bot_state_guard.paused {
+                "Monitoring is not paused."
+            } else {
+                bot_state_guard.paused = false;
+                info!("Monitoring resumed for Chat ID: {}", chat_id);
+                "Monitoring resumed."
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Check => {
+            let bot_state_guard = bot_state.lock().await;
+            let reply = if bot_state_guard.task.is_none() {
+                "No task is running."
+            } else if bot_state_guard.paused {
+                "Monitoring is paused, /resume first."
+            } else {
+                // a permit is stored if the loop is busy, it then checks again right after the current cycle
+                bot_state_guard.check_now.notify_one();
+                info!("Immediate check requested by Chat ID: {}", chat_id);
+                "Checking all hosts now, changes are notified as usual."
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Reset => {
+            let count = {
+                let mut app_state_guard = app_state.lock().await;
+                for host in app_state_guard.hosts.values_mut() {
+                    host.state.reset_alarms();
                 }
-
-                bot_state_guard.chat_id = Some(chat_id);
-                info!("Host monitoring task started. \nChat ID: {}", chat_id);
-
-                let (tx, rx) = oneshot::channel();
-                bot_state_guard.task = Some(tx);
-                let bot_config = bot_state_guard.config.clone();
-                let bot_clone = bot.clone();
-                let app_state_clone = Arc::clone(&app_state);
-                let bot_state_clone = Arc::clone(&bot_state);
-
-                tokio::spawn(async move {
-                    let mut rx = rx;
-                    let mut ping_args = {
-                        let bot_state_guard = bot_state_clone.lock().await;
-                        bot_state_guard.config.ping_args.clone()
-                    };
-                    loop {
-                        tokio::select! {
-                            _ = &mut rx => {
-                                info!("Task for Chat ID {} stopped", chat_id);
-                                break;
-                            }
-                            _ = sleep(Duration::from_secs(bot_config.ping_interval)) => {
-                                let hosts = {
-                                    let app_state_guard = app_state_clone.lock().await;
-                                    app_state_guard.hosts.clone()
-                                };
-                                for (address, online) in hosts {
-                                    if online {
-                                        ping_args.push(address.to_string());
-                                        let output = Command::new("ping")
-                                            .args(&ping_args)
-                                            .output()
-                                            .await;
-                                        match output {
-                                            Ok(output) => {
-                                                let stdout = String::from_utf8_lossy(&output.stdout);
-                                                if !output.status.success() {
-                                                    let mut app_state_guard = app_state_clone.lock().await;
-                                                    app_state_guard.hosts.insert(address, false);
-                                                    let _ = bot_clone
-                                                        .send_message(chat_id, &format!("HOST OFFLINE -> STDOUT {}", &stdout))
-                                                        .await;
-                                                }
-                                            }
-                                            Err(e) => info!("PING ERROR => {}", e),
-                                        }
-                                        ping_args.pop();
-                                    }
-                                }
-                            }
+                host_state::save_state(&app_state_guard.state_path, &app_state_guard.hosts);
+                app_state_guard.hosts.len()
+            };
+            warn!("Alarms of {} hosts reset by Chat ID: {}", count, chat_id);
+            let bot_state_guard = bot_state.lock().await;
+            let reply = if bot_state_guard.task.is_none() {
+                format!("Reset {} hosts to online. No task is running, /start to check them.", count)
+            } else if bot_state_guard.paused {
+                format!("Reset {} hosts to online. Monitoring is paused, /resume to check them.", count)
+            } else {
+                bot_state_guard.check_now.notify_one();
+                format!("Reset {} hosts to online, checking all hosts now. Hosts still down are alerted again.", count)
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Silence(args) => {
+            let arg = args.split_whitespace().next().map(str::to_lowercase);
+            let mut bot_state_guard = bot_state.lock().await;
+            let reply = match arg.as_deref() {
+                Some("on") => {
+                    bot_state_guard.silenced = true;
+                    warn!("Notifications silenced by Chat ID: {}", chat_id);
+                    "All notifications silenced, checks keep running. /silence off to resume them."
+                }
+                Some("off") => {
+                    bot_state_guard.silenced = false;
+                    info!("Notifications unsilenced by Chat ID: {}", chat_id);
+                    "Notifications resumed."
+                }
+                None if bot_state_guard.silenced => "Notifications are silenced. Usage: /silence on|off",
+                None => "Notifications are on. Usage: /silence on|off",
+                Some(_) => "Usage: /silence on|off",
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Quiethours(args) => {
+            // /quiethours 22:00-07:00 or 22:00-07:00@Sat,Sun sets the window, /quiethours off removes it
+            let reply = match args.split_whitespace().next() {
+                None =>
+                    match &bot_state.lock().await.config.quiet_hours {
+                        Some(schedule) =>
+                            format!(
+                                "Quiet hours {}, only outages are sent then. Usage: /quiethours HH:MM-HH:MM[@days]|off",
+                                schedule
+                            ),
+                        None => "No quiet hours set. Usage: /quiethours HH:MM-HH:MM[@days]|off".to_string(),
+                    }
+                Some(arg) if arg.eq_ignore_ascii_case("off") => {
+                    let saved = set_quiet_hours(&bot_state, None).await;
+                    saved_reply("Quiet hours removed, all notifications are sent at any time.".to_string(), saved)
+                }
+                Some(arg) =>
+                    match maintenance::Schedule::parse(arg) {
+                        Ok(schedule) => {
+                            let reply = format!(
+                                "Quiet hours set to {}, only outages are sent during them.",
+                                schedule
+                            );
+                            saved_reply(reply, set_quiet_hours(&bot_state, Some(schedule)).await)
                         }
+                        Err(e) => format!("Invalid quiet hours: {}", e),
                     }
-                    let mut bot_state_guard = bot_state_clone.lock().await;
-                    bot_state_guard.task = None;
-                });
-
-                bot.send_message(
-                    chat_id,
-                    format!("Notification Bot started. Your chat ID is: {}", chat_id)
-                ).await?;
-            } else if text.starts_with("/stop") {
-                let mut bot_state_guard = bot_state.lock().await;
-                if let Some(tx) = bot_state_guard.task.take() {
-                    if tx.send(()).is_ok() {
-                        bot.send_message(chat_id, "Task stopped.").await?;
-                        info!("Task stopped for Chat ID: {}", chat_id);
-                    } else {
-                        bot.send_message(chat_id, "Failed to stop task.").await?;
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Fastcheck(args) => {
+            // /fastcheck <seconds> <duration> checks more often for a while, /fastcheck off ends it early
+            let args = args.split_whitespace().collect::<Vec<_>>();
+            let mut bot_state_guard = bot_state.lock().await;
+            let reply = match args.as_slice() {
+                [arg] if arg.eq_ignore_ascii_case("off") => {
+                    bot_state_guard.fast_check = None;
+                    bot_state_guard.interval_changed.notify_waiters();
+                    format!("Back to checking every {}s", bot_state_guard.config.ping_interval)
+                }
+                [interval, duration] => {
+                    match (interval.parse::<u64>(), host_state::parse_duration(duration)) {
+                        (Ok(interval), _) if interval < MIN_PING_INTERVAL => {
+                            format!("Interval must be at least {} seconds", MIN_PING_INTERVAL)
+                        }
+                        (Ok(interval), Some(duration)) if duration > chrono::Duration::zero() => {
+                            let until = Instant::now() + duration.to_std().unwrap_or_default();
+                            bot_state_guard.fast_check = Some((interval, until));
+                            bot_state_guard.interval_changed.notify_waiters();
+                            info!("Fast check every {}s for {} by Chat ID {}", interval, duration, chat_id);
+                            format!(
+                                "Checking every {}s for {}, then back to every {}s",
+                                interval,
+                                host_state::format_duration(duration),
+                                bot_state_guard.config.ping_interval
+                            )
+                        }
+                        _ => "Usage: /fastcheck <seconds> <duration>, eg. /fastcheck 10 15m".to_string(),
                     }
-                } else {
-                    bot.send_message(chat_id, "No task is running.").await?;
                 }
-            } else if text.starts_with("/add") {
-                bot.send_message(chat_id, "Enter hostname you want to add.").await?;
-
-                if let Err(e) = dialogue.update(DialogueState::WaitingForHostAdd).await {
-                    info!("Dialogue update error: {}", e);
+                _ => "Usage: /fastcheck <seconds> <duration>, eg. /fastcheck 10 15m".to_string(),
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Setinterval(args) => {
+            let reply = match args.split_whitespace().next() {
+                Some(value) => set_ping_interval(&bot_state, value).await,
+                None => {
+                    let interval = bot_state.lock().await.config.ping_interval;
+                    format!("Ping interval is {}s. Usage: /setinterval <seconds>", interval)
                 }
-                return Ok(());
-            } else if text.starts_with("/remove") {
-                let hosts = {
-                    let app_state_guard = app_state.lock().await;
-                    app_state_guard.hosts.clone()
-                };
-                let hosts_string = hosts
-                    .iter()
-                    .map(|(host, _)| format!("{}", host))
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                bot.send_message(
-                    chat_id,
-                    format!("Enter hostname you want to remove.\n{}", hosts_string)
-                ).await?;
-                if let Err(e) = dialogue.update(DialogueState::WaitingForHostRemove).await {
-                    info!("Dialogue update error: {}", e);
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Setalertchat(args) => {
+            // /setalertchat uses this chat, /setalertchat <chat id> another one, /setalertchat off resets
+            let reply = match args.split_whitespace().next() {
+                Some(arg) if arg.eq_ignore_ascii_case("off") => {
+                    let saved = set_alert_chat(&bot_state, None).await;
+                    saved_reply("Alerts are sent to the chat that started monitoring.".to_string(), saved)
                 }
-
-                return Ok(());
-            } else if text.starts_with("/hosts") {
-                let hosts = {
-                    let app_state_guard = app_state.lock().await;
-                    app_state_guard.hosts.clone()
-                };
-
-                let hosts_string = hosts
-                    .iter()
-                    .enumerate()
-                    .map(|(index, (host, _))| format!(" {}: {}", index + 1, host))
-                    .collect::<Vec<_>>()
-                    .join("\n");
-
-                bot.send_message(chat_id, format!("Hosts: \n {}", hosts_string)).await?;
-                info!("Listed hosts \n{} ", hosts_string);
-
-                return Ok(());
-            } else if text.starts_with("/config") {
-                let input = text;
-                let args: Vec<&str> = input.split(" ").collect();
-                if args.len() > 1 {
-                    match args[1] {
-                        "edit" => {
-                            if let Some(_) = args.get(2..4) {
-                                let mut bot_state_guard = bot_state.lock().await;
-                                let field = args[2];
-                                let value = args[3];
-                                match field {
-                                    "ping_interval" => {
-                                        match value.parse::<u64>() {
-                                            Ok(value) => {
-                                                bot_state_guard.config.ping_interval = value;
-                                                bot.send_message(
-                                                    chat_id,
-                                                    format!("Ping interval changed to {}", value)
-                                                ).await?;
-                                                // write new config to file
-                                                let toml_config = toml
-                                                    ::to_string(&bot_state_guard.config)
-                                                    .unwrap();
-                                                fs::write(
-                                                    "config.toml",
-                                                    toml_config
-                                                ).await.unwrap();
-                                            }
-                                            Err(e) => {
-                                                bot.send_message(
-                                                    chat_id,
-                                                    format!("Invalid value: {}", e)
-                                                ).await?;
-                                            }
-                                        }
-                                    }
-                                    "ping_args" => {
-                                        // parse arguments from message
-                                        let joined = args[3..].join(" ");
-                                        let value = joined.trim_matches('"');
-                                        let mut ping_args: Vec<String> = value
-                                            .split_whitespace()
-                                            .map(|s| s.to_string())
-                                            .collect();
-                                        
-                                        debug!("new ping args : {:?}", &ping_args);
-                                        // test ping args
-                                        ping_args.push("127.0.0.1".to_string());
-                                        let output = Command::new("ping")
-                                            .args(&ping_args)
-                                            .output().await;
-                                        match output {
-                                            Ok(output) => {
-                                                let exit_code = output.status.code().unwrap();
-                                                debug!("exit code : {:?}", &exit_code);
-                                                if exit_code == 0 {
-                                                    ping_args.pop();
-                                                    let ping_args_clone = ping_args.clone();
-                                                    bot_state_guard.config.ping_args = ping_args;
-                                                    // write new config to file
-                                                    let toml_config = toml
-                                                        ::to_string(&bot_state_guard.config)
-                                                        .unwrap();
-                                                    fs::write(
-                                                        "config.toml",
-                                                        toml_config
-                                                    ).await.unwrap();
-                                                    bot.send_message(
-                                                        chat_id,
-                                                        format!(
-                                                            "Ping arguments set : {:?}",
-                                                            &ping_args_clone
-                                                        )
-                                                    ).await?;
-                                                } else {
-                                                    bot.send_message(
-                                                        chat_id,
-                                                        format!(
-                                                            "Invalid arguments ->{:?}",
-                                                            String::from_utf8_lossy(
-                                                                &output.stderr
-                                                            ).trim_end_matches(&['\r', '\n'][..])
-                                                        )
-                                                    ).await?;
-                                                }
-                                            }
-                                            Err(e) => info!("PING ERROR => {}", e),
-                                        }
-                                    }
-                                    _ => {
-                                        bot.send_message(chat_id, "Invalid arguments").await?;
-                                    }
-                                }
-
-                                debug!("edit_args: {:?}", args);
-                            } else {
-                                bot.send_message(chat_id, "Not enought arguments").await?;
-                            }
-                        }
-                        "list" => {
-                            let bot_config = {
-                                let bot_state_guard = bot_state.lock().await;
-                                bot_state_guard.config.clone()
-                            };
-                            bot.send_message(chat_id, format!("{:?}", bot_config)).await?;
-                        }
-                        _ => {
-                            bot.send_message(chat_id, "Invalid input").await?;
+                Some(arg) =>
+                    match arg.parse::<i64>() {
+                        Ok(id) => {
+                            let saved = set_alert_chat(&bot_state, Some(id)).await;
+                            saved_reply(format!("Alerts are now sent to chat {}", id), saved)
                         }
+                        Err(e) => format!("Invalid chat ID: {}", e),
                     }
-                } else {
+                None => {
+                    let saved = set_alert_chat(&bot_state, Some(chat_id.0)).await;
+                    saved_reply(format!("Alerts are now sent to this chat ({})", chat_id), saved)
+                }
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Prefs(args) => {
+            // /prefs severity <info|warning|critical>, /prefs verbosity <full|brief>, /prefs reset
+            let args = args.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>();
+            let mut bot_state_guard = bot_state.lock().await;
+            let key = chat_id.to_string();
+            let mut preferences = bot_state_guard.config.chat_preferences.get(&key).cloned().unwrap_or_default();
+            let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+            let changed = match args.as_slice() {
+                [] => false,
+                ["reset"] => {
+                    preferences = ChatPreferences::default();
+                    true
+                }
+                ["severity", "info"] => {
+                    preferences.min_severity = Severity::Info;
+                    true
+                }
+                ["severity", "warning"] => {
+                    preferences.min_severity = Severity::Warning;
+                    true
+                }
+                ["severity", "critical"] => {
+                    preferences.min_severity = Severity::Critical;
+                    true
+                }
+                ["verbosity", "full"] => {
+                    preferences.verbosity = Verbosity::Full;
+                    true
+                }
+                ["verbosity", "brief"] => {
+                    preferences.verbosity = Verbosity::Brief;
+                    true
+                }
+                _ => {
+                    drop(bot_state_guard);
                     bot.send_message(
                         chat_id,
-                        "/config list     - Show current config \n /config edit <field> <value>     - Update config field"
+                        "Usage: /prefs severity info|warning|critical, /prefs verbosity full|brief or /prefs reset"
                     ).await?;
+                    return Ok(());
                 }
-
-                return Ok(());
-            }
-        }
-        DialogueState::WaitingForPassword => {
-            let password = {
-                let app_state_guard = app_state.lock().await;
-                app_state_guard.password.clone()
             };
-
-            if text == password {
-                {
-                    let mut app_state_guard = app_state.lock().await;
-                    app_state_guard.allowed_chats.push(chat_id);
-                }
-                bot.send_message(
-                    chat_id,
-                    "Password accepted! You can now use /start, /stop, /status, /hosts, /add, /remove."
-                ).await?;
-                if let Err(e) = dialogue.update(DialogueState::Default).await {
-                    info!("Dialogue update error: {}", e);
+            if changed {
+                let chat_preferences = &mut bot_state_guard.config.chat_preferences;
+                if matches!(
+                    preferences,
+                    ChatPreferences { min_severity: Severity::Info, verbosity: Verbosity::Full }
+                ) {
+                    chat_preferences.remove(&key);
+                } else {
+                    chat_preferences.insert(key, preferences.clone());
                 }
-            } else {
-                bot.send_message(chat_id, "Incorrect password. Try again.").await?;
             }
+            let saved = if changed {
+                info!("Notification preferences of Chat ID {} set to {:?}", chat_id, preferences);
+                save_config(&bot_state_guard.config).await
+            } else {
+                Ok(())
+            };
+            drop(bot_state_guard);
+            let reply = format!(
+                "Notifications for this chat: {:?} and above, {:?} messages",
+                preferences.min_severity,
+                preferences.verbosity
+            ).to_lowercase();
+            bot.send_message(chat_id, saved_reply(reply, saved)).await?;
         }
+        BotCommand::Add => {
+            bot.send_message(chat_id, "Enter hostname you want to add.").await?;
 
-        DialogueState::WaitingForHostAdd => {
-            let mut new_host = "\n".to_string();
-            new_host.push_str(text);
-
-            let mut app_state_guard = app_state.lock().await;
-            // add new host to hosts file
-            let mut paths_file = OpenOptions::new()
-                .append(true)
-                .open(app_state_guard.hosts_path.clone())
-                .expect("cannot open file");
-
-            paths_file.write(new_host.as_bytes()).expect("Write failed to hosts.txt");
-
-            // set app_sate.hosts with updated hosts file
-            app_state_guard.hosts = read_to_string(app_state_guard.hosts_path.clone())
-                .unwrap()
-                .lines()
-                .map(String::from)
-                .collect::<HashSet<_>>()
-                .into_iter()
-                .map(|host| (host, true))
-                .collect();
-            info!("New hosts for {} -> {:?}", chat_id, app_state_guard.hosts);
-
-            bot.send_message(chat_id, "New host added.").await?;
-            info!("Added {} from hosts", new_host);
-
-            if let Err(e) = dialogue.update(DialogueState::Default).await {
+            if let Err(e) = dialogue.update(DialogueState::WaitingForHostAdd).await {
                 info!("Dialogue update error: {}", e);
             }
+            return Ok(());
         }
-
-        DialogueState::WaitingForHostRemove => {
-            let host_remove = text;
-            let mut app_state_guard = app_state.lock().await;
-
-            // remove hosts from app_state.hosts
-            if app_state_guard.hosts.remove(host_remove).is_none() {
-                bot.send_message(chat_id, format!("Host '{}' not found.", host_remove)).await?;
-                if let Err(e) = dialogue.update(DialogueState::Default).await {
-                    info!("Dialogue update error: {}", e);
+        BotCommand::Addfrom(args) => {
+            if !args.trim().eq_ignore_ascii_case("arp") {
+                bot.send_message(chat_id, "Usage: /addfrom arp").await?;
+                return Ok(());
+            }
+            let candidates = match arp_candidates(&app_state).await {
+                Ok(candidates) => candidates,
+                Err(e) => {
+                    error!("{}", e);
+                    bot.send_message(chat_id, format!("Unable to read the neighbor table: {}", e)).await?;
+                    return Ok(());
                 }
+            };
+            if candidates.is_empty() {
+                bot.send_message(chat_id, "No hosts in the neighbor table that aren't monitored yet.").await?;
                 return Ok(());
             }
-
-            // generate updated hosts file string
-            let hosts: Vec<&str> = app_state_guard.hosts
+            let mut buttons = candidates
+                .iter()
+                .take(ADDFROM_MAX_BUTTONS)
+                .map(|address| {
+                    vec![
+                        InlineKeyboardButton::callback(
+                            address.as_str(),
+                            format!("{}{}", ADD_CALLBACK_PREFIX, address)
+                        )
+                    ]
+                })
+                .collect::<Vec<_>>();
+            buttons.push(vec![InlineKeyboardButton::callback("Add all", "addfrom:arp")]);
+            let text = format!(
+                "{} hosts in the neighbor table aren't monitored yet, press one to add it:\n{}",
+                candidates.len(),
+                candidates.join("\n")
+            );
+            let chunks = split_message(&text, MAX_MESSAGE_LENGTH);
+            let last = chunks.len().saturating_sub(1);
+            for (index, chunk) in chunks.into_iter().enumerate() {
+                let message = bot.send_message(chat_id, chunk);
+                if index == last {
+                    message.reply_markup(InlineKeyboardMarkup::new(buttons.clone())).await?;
+                } else {
+                    message.await?;
+                }
+            }
+        }
+        BotCommand::Remove => {
+            let hosts = {
+                let app_state_guard = app_state.lock().await;
+                app_state_guard.hosts.clone()
+            };
+            let hosts_string = hosts
                 .keys()
-                .map(|host| host.as_str()) // Convert &String to &str
-                .collect();
-            let updated_hosts = hosts.join("\n");
-
-            // write new hosts file
-            let mut hosts_file = OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .open(&app_state_guard.hosts_path)
-                .expect("Cant open file");
-            hosts_file
-                .write_all(updated_hosts.as_bytes())
-                .expect("Cant open hosts.txt for writing");
-            bot.send_message(chat_id, format!("Host '{}' removed.", host_remove)).await?;
-            info!("Removed {} from hosts", host_remove);
-
-            if let Err(e) = dialogue.update(DialogueState::Default).await {
+                .map(|host| host.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            // one button per host, hosts too long for callback data can still be typed
+            let mut sorted_hosts = hosts.keys().collect::<Vec<_>>();
+            sorted_hosts.sort();
+            let buttons = sorted_hosts
+                .into_iter()
+                .filter(|host| REMOVE_CALLBACK_PREFIX.len() + host.len() <= MAX_CALLBACK_DATA_LENGTH)
+                .map(|host| {
+                    vec![
+                        InlineKeyboardButton::callback(
+                            host.as_str(),
+                            format!("{}{}", REMOVE_CALLBACK_PREFIX, host)
+                        )
+                    ]
+                })
+                .collect::<Vec<_>>();
+            bot.send_message(
+                chat_id,
+                format!("Enter hostname you want to remove.\n{}", hosts_string)
+            )
+                .reply_markup(InlineKeyboardMarkup::new(buttons)).await?;
+            if let Err(e) = dialogue.update(DialogueState::WaitingForHostRemove).await {
                 info!("Dialogue update error: {}", e);
             }
+
+            return Ok(());
+        }
+        BotCommand::Hosts => {
+            let hosts = {
+                let app_state_guard = app_state.lock().await;
+                app_state_guard.hosts.clone()
+            };
+
+            let hosts_string = host_list(hosts.values());
+
+            bot.send_message(chat_id, format!("Hosts: \n {}", hosts_string)).await?;
+            info!("Listed hosts \n{} ", hosts_string);
+
+            return Ok(());
+        }
+        BotCommand::Pending => {
+            let pending = pending_hosts(&app_state.lock().await.hosts);
+            let reply = if pending.is_empty() {
+                "Every host has been checked.".to_string()
+            } else {
+                format!(
+                    "{} hosts not checked since start, assumed online:\n{}",
+                    pending.len(),
+                    pending
+                        .iter()
+                        .map(|name| format!(" {}", name))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                )
+            };
+            send_chunked(&bot, chat_id, &reply).await?;
+        }
+        BotCommand::Find(args) => {
+            let query = Some(args.trim()).filter(|query| !query.is_empty());
+            let Some(query) = query else {
+                bot.send_message(chat_id, "Usage: /find <text>").await?;
+                return Ok(());
+            };
+            let query = query.to_lowercase();
+            let hosts = {
+                let app_state_guard = app_state.lock().await;
+                app_state_guard.hosts.clone()
+            };
+            let matches = hosts.values().filter(|host| {
+                host.entry.address.to_lowercase().contains(&query) ||
+                    host.entry.label.as_ref().is_some_and(|label| label.to_lowercase().contains(&query))
+            });
+            let hosts_string = host_list(matches);
+            let reply = if hosts_string.is_empty() {
+                format!("No hosts match '{}'", query)
+            } else {
+                format!("Hosts matching '{}': \n {}", query, hosts_string)
+            };
+            send_chunked(&bot, chat_id, &reply).await?;
+        }
+        BotCommand::Down | BotCommand::Up => {
+            // current monitoring state only, no scan
+            let online = matches!(cmd, BotCommand::Up);
+            let mut lines = {
+                let app_state_guard = app_state.lock().await;
+                let mut lines = app_state_guard.hosts
+                    .values()
+                    // pending hosts are only assumed online
+                    .filter(|host| host.state.online == online && !(online && host.state.pending))
+                    .map(|host| {
+                        let critical = if host.entry.critical { " [CRITICAL]" } else { "" };
+                        let name = format!("{}{}", host.entry.display_name(), critical);
+                        let state = &host.state;
+                        if online {
+                            return format!(" {}", name);
+                        }
+                        let duration = state.offline_since
+                            .map(|since| format!(" for {}", host_state::format_duration(chrono::Utc::now() - since)))
+                            .unwrap_or_default();
+                        let ack = if state.acknowledged { " [ACK]" } else { "" };
+                        format!(" {} offline{}{}", name, duration, ack)
+                    })
+                    .collect::<Vec<_>>();
+                // addresses of an online subnet that stopped answering
+                if !online {
+                    for host in app_state_guard.hosts.values().filter(|host| host.state.online) {
+                        for (member, since) in &host.state.subnet_down {
+                            let duration = host_state::format_duration(chrono::Utc::now() - *since);
+                            lines.push(format!(" {} in {} offline for {}", member, host.entry.display_name(), duration));
+                        }
+                    }
+                }
+                lines
+            };
+            lines.sort();
+            let reply = match (online, lines.is_empty()) {
+                (true, true) => "No hosts are online.".to_string(),
+                (false, true) => "All hosts are online.".to_string(),
+                (true, false) => format!("{} hosts online:\n{}", lines.len(), lines.join("\n")),
+                (false, false) => format!("{} hosts offline:\n{}", lines.len(), lines.join("\n")),
+            };
+            send_chunked(&bot, chat_id, &reply).await?;
+        }
+        BotCommand::Invite(args) => {
+            // /invite [viewer] [duration], the token is valid once
+            let mut role = Role::Admin;
+            let mut valid_for = INVITE_DEFAULT_VALIDITY;
+            for arg in args.split_whitespace() {
+                if arg.eq_ignore_ascii_case("viewer") {
+                    role = Role::Viewer;
+                } else if let Some(duration) = host_state::parse_duration(arg) && let Ok(duration) = duration.to_std() {
+                    valid_for = duration;
+                } else {
+                    bot.send_message(chat_id, "Usage: /invite [viewer] [duration], eg. /invite viewer 1h").await?;
+                    return Ok(());
+                }
+            }
+            let token = rand::rng()
+                .sample_iter(rand::distr::Alphanumeric)
+                .take(INVITE_TOKEN_LENGTH)
+                .map(char::from)
+                .collect::<String>();
+            app_state.lock().await.invites.insert(token.clone(), (role, Instant::now() + valid_for));
+            info!("Chat ID {} created a {:?} invite valid for {}s", chat_id, role, valid_for.as_secs());
+            bot.send_message(
+                chat_id,
+                format!(
+                    "Invite token for {} access, valid once for {}:\n{}\nThe new user sends it to the bot when asked for the password.",
+                    if role == Role::Admin { "full" } else { "read only" },
+                    host_state::format_duration(chrono::Duration::from_std(valid_for).unwrap_or_default()),
+                    token
+                )
+            ).await?;
+        }
+        BotCommand::Top(args) => {
+            // /top [count] [downtime], ranked by number of outages unless downtime is given
+            let mut limit = TOP_DEFAULT_COUNT;
+            let mut by_downtime = false;
+            for arg in args.split_whitespace() {
+                if arg.eq_ignore_ascii_case("downtime") {
+                    by_downtime = true;
+                } else if let Ok(count) = arg.parse::<usize>() && count > 0 {
+                    limit = count;
+                } else {
+                    bot.send_message(chat_id, "Usage: /top [count] [downtime]").await?;
+                    return Ok(());
+                }
+            }
+            let outage_days = bot_state.lock().await.config.retention.outage_days;
+            let mut ranking = {
+                let app_state_guard = app_state.lock().await;
+                app_state_guard.hosts
+                    .values()
+                    .map(|host| {
+                        let (count, downtime) = host.state.outage_totals(outage_days);
+                        (host.entry.display_name(), count, downtime)
+                    })
+                    .filter(|(_, count, _)| *count > 0)
+                    .collect::<Vec<_>>()
+            };
+            if by_downtime {
+                ranking.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)).then(a.0.cmp(&b.0)));
+            } else {
+                ranking.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+            }
+            let reply = if ranking.is_empty() {
+                format!("No outages in the last {} days.", outage_days)
+            } else {
+                let lines = ranking
+                    .iter()
+                    .take(limit)
+                    .enumerate()
+                    .map(|(index, (name, count, downtime))| {
+                        format!(
+                            " {}: {} - {} outages, {} down",
+                            index + 1,
+                            name,
+                            count,
+                            host_state::format_duration(*downtime)
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "Hosts with the most {} in the last {} days:\n{}",
+                    if by_downtime { "downtime" } else { "outages" },
+                    outage_days,
+                    lines.join("\n")
+                )
+            };
+            send_chunked(&bot, chat_id, &reply).await?;
+        }
+        BotCommand::Label(args) => {
+            // /label <host> <name> sets the label, /label <host> clears it
+            let mut args = args.splitn(2, char::is_whitespace);
+            let Some(host) = args.next().filter(|host| !host.is_empty()) else {
+                bot.send_message(chat_id, "Usage: /label <host> [name]").await?;
+                return Ok(());
+            };
+            let label = args
+                .next()
+                .map(|label| label.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|label| !label.is_empty());
+            if label.as_deref().is_some_and(|label| label.contains('=')) {
+                bot.send_message(chat_id, "Labels can't contain '='.").await?;
+                return Ok(());
+            }
+
+            let mut app_state_guard = app_state.lock().await;
+            let Some(entry) = app_state_guard.hosts.get_mut(host).map(|host| &mut host.entry) else {
+                bot.send_message(chat_id, format!("{} is not a monitored host.", host)).await?;
+                return Ok(());
+            };
+            entry.label = label;
+            let reply = match &entry.label {
+                Some(label) => format!("{} is now labeled {}", host, label),
+                None => format!("Label of {} removed", host),
+            };
+            if let Err(e) = hosts::save_hosts(&app_state_guard.hosts_path, &app_state_guard.hosts) {
+                let hosts_path = app_state_guard.hosts_path.display().to_string();
+                error!("Unable to write {} => {}", hosts_path, e);
+                drop(app_state_guard);
+                bot.send_message(chat_id, format!("{}, but {} could not be written: {}", reply, hosts_path, e)).await?;
+                return Ok(());
+            }
+            info!("{}", reply);
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Subnet(args) => {
+            let usage = "Usage: /subnet <cidr> [label], eg. /subnet 10.0.0.0/28 Office-LAN";
+            let entry = match HostEntry::parse(args) {
+                Ok(entry) if matches!(entry.method, CheckMethod::Subnet { .. }) => entry,
+                _ if args.trim().is_empty() => {
+                    bot.send_message(chat_id, usage).await?;
+                    return Ok(());
+                }
+                Ok(_) => {
+                    bot.send_message(chat_id, usage).await?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    bot.send_message(chat_id, format!("Invalid subnet: {}", e)).await?;
+                    return Ok(());
+                }
+            };
+            let name = entry.display_name();
+            let reply = match add_host(&bot_state, &app_state, entry).await {
+                Ok(result) => {
+                    let templates = bot_state.lock().await.config.templates.clone();
+                    messages::host_added_message(&templates, &name, &result)
+                }
+                Err(reason) => reason.to_string(),
+            };
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Critical(args) => {
+            let args = args.split_whitespace().collect::<Vec<_>>();
+            let (host, critical) = match args.as_slice() {
+                [host, arg] if arg.eq_ignore_ascii_case("on") => (*host, true),
+                [host, arg] if arg.eq_ignore_ascii_case("off") => (*host, false),
+                _ => {
+                    bot.send_message(chat_id, "Usage: /critical <host> on|off").await?;
+                    return Ok(());
+                }
+            };
+
+            let mut app_state_guard = app_state.lock().await;
+            let Some(entry) = app_state_guard.hosts.get_mut(host).map(|host| &mut host.entry) else {
+                bot.send_message(chat_id, format!("{} is not a monitored host.", host)).await?;
+                return Ok(());
+            };
+            entry.critical = critical;
+            let reply = if critical {
+                format!("{} is critical, its alerts get through /silence, quiet hours and maintenance windows", host)
+            } else {
+                format!("{} is no longer critical", host)
+            };
+            if let Err(e) = hosts::save_hosts(&app_state_guard.hosts_path, &app_state_guard.hosts) {
+                let hosts_path = app_state_guard.hosts_path.display().to_string();
+                error!("Unable to write {} => {}", hosts_path, e);
+                drop(app_state_guard);
+                bot.send_message(chat_id, format!("{}, but {} could not be written: {}", reply, hosts_path, e)).await?;
+                return Ok(());
+            }
+            info!("{}", reply);
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Note(args) => {
+            // /note <host> <text> sets the note, /note <host> clears it
+            let mut args = args.splitn(2, char::is_whitespace);
+            let Some(host) = args.next().filter(|host| !host.is_empty()) else {
+                bot.send_message(chat_id, "Usage: /note <host> [text]").await?;
+                return Ok(());
+            };
+            let note = args
+                .next()
+                .map(|note| note.trim().to_string())
+                .filter(|note| !note.is_empty());
+
+            let mut app_state_guard = app_state.lock().await;
+            let Some(entry) = app_state_guard.hosts.get_mut(host).map(|host| &mut host.entry) else {
+                bot.send_message(chat_id, format!("{} is not a monitored host.", host)).await?;
+                return Ok(());
+            };
+            entry.note = note;
+            let reply = match &entry.note {
+                Some(_) => format!("Note saved for {}", host),
+                None => format!("Note of {} removed", host),
+            };
+            if let Err(e) = hosts::save_hosts(&app_state_guard.hosts_path, &app_state_guard.hosts) {
+                let hosts_path = app_state_guard.hosts_path.display().to_string();
+                error!("Unable to write {} => {}", hosts_path, e);
+                drop(app_state_guard);
+                bot.send_message(chat_id, format!("{}, but {} could not be written: {}", reply, hosts_path, e)).await?;
+                return Ok(());
+            }
+            info!("{}", reply);
+            bot.send_message(chat_id, reply).await?;
+        }
+        BotCommand::Export => {
+            let hosts_path = app_state.lock().await.hosts_path.clone();
+            let hosts_content = match fs::read(&hosts_path).await {
+                Ok(content) => content,
+                Err(e) => {
+                    error!("Unable to read {} => {}", hosts_path.display(), e);
+                    bot.send_message(chat_id, format!("Unable to read {}: {}", hosts_path.display(), e)).await?;
+                    return Ok(());
+                }
+            };
+            let hosts_file_name = hosts_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or("hosts.txt".to_string());
+            bot.send_document(chat_id, InputFile::memory(hosts_content).file_name(hosts_file_name)).await?;
+
+            let config_content = match fs::read_to_string(CONFIG_PATH).await {
+                Ok(content) => content,
+                Err(e) => {
+                    error!("Unable to read {} => {}", CONFIG_PATH, e);
+                    bot.send_message(chat_id, format!("Unable to read {}: {}", CONFIG_PATH, e)).await?;
+                    return Ok(());
+                }
+            };
+            bot.send_document(
+                chat_id,
+                InputFile::memory(redact_config(&config_content)).file_name(CONFIG_PATH)
+            ).await?;
+            info!("Exported hosts and config to Chat ID: {}", chat_id);
+        }
+        BotCommand::Import(args) => {
+            // /import merges new hosts into the list, /import replace swaps the whole list
+            let replace = args
+                .split_whitespace()
+                .next()
+                .is_some_and(|mode| mode.eq_ignore_ascii_case("replace"));
+            let prompt = if replace {
+                "Send the hosts file (.txt, .json or .yaml) to replace the host list, or /cancel."
+            } else {
+                "Send the hosts file (.txt, .json or .yaml) to add its hosts, or /cancel."
+            };
+            bot.send_message(chat_id, prompt).await?;
+
+            if let Err(e) = dialogue.update(DialogueState::WaitingForImport { replace }).await {
+                info!("Dialogue update error: {}", e);
+            }
+            return Ok(());
+        }
+        BotCommand::Migrate(args) => {
+            // /migrate [json|yaml|text] rewrites the hosts file in another format, json by default
+            let format = match args.split_whitespace().next() {
+                Some(name) =>
+                    match HostsFormat::from_name(name) {
+                        Some(format) => format,
+                        None => {
+                            bot.send_message(chat_id, "Usage: /migrate [json|yaml|text]").await?;
+                            return Ok(());
+                        }
+                    }
+                None => HostsFormat::Json,
+            };
+
+            let mut app_state_guard = app_state.lock().await;
+            let old_path = app_state_guard.hosts_path.clone();
+            if HostsFormat::from_path(&old_path) == format {
+                bot.send_message(chat_id, format!("Hosts are already stored in {}", old_path.display())).await?;
+                return Ok(());
+            }
+            let new_path = old_path.with_extension(format.extension());
+            if let Err(e) = hosts::save_hosts(&new_path, &app_state_guard.hosts) {
+                error!("Unable to write hosts to {} => {}", new_path.display(), e);
+                bot.send_message(chat_id, format!("Unable to write {}: {}", new_path.display(), e)).await?;
+                return Ok(());
+            }
+            app_state_guard.hosts_path = new_path.clone();
+            drop(app_state_guard);
+
+            let mut bot_state_guard = bot_state.lock().await;
+            bot_state_guard.config.hosts_path = Some(new_path.clone());
+            let saved = save_config(&bot_state_guard.config).await;
+            info!("Migrated hosts from {} to {}", old_path.display(), new_path.display());
+            let reply = format!(
+                "Hosts migrated to {}, {} is kept but no longer read.",
+                new_path.display(),
+                old_path.display()
+            );
+            bot.send_message(chat_id, saved_reply(reply, saved)).await?;
+        }
+        BotCommand::Ack(args) => {
+            let Some(host) = args.split_whitespace().next() else {
+                bot.send_message(chat_id, "Usage: /ack <host>").await?;
+                return Ok(());
+            };
+
+            let reply = {
+                let mut app_state_guard = app_state.lock().await;
+                match app_state_guard.hosts.get_mut(host).map(|host| &mut host.state) {
+                    None => format!("Host '{}' not found.", host),
+                    Some(state) if state.online => format!("Host '{}' is not offline.", host),
+                    Some(state) => {
+                        state.acknowledged = true;
+                        host_state::save_state(
+                            &app_state_guard.state_path,
+                            &app_state_guard.hosts
+                        );
+                        info!("Outage of {} acknowledged by Chat ID: {}", host, chat_id);
+                        format!("Outage of '{}' acknowledged. No reminders until it recovers.", host)
+                    }
+                }
+            };
+            bot.send_message(chat_id, reply).await?;
+
+            return Ok(());
+        }
+        BotCommand::Snooze(args) => {
+            let usage = "Usage: /snooze <host> <duration>|off, eg. /snooze 10.0.0.5 30m";
+            let args = args.split_whitespace().collect::<Vec<_>>();
+            let [host, duration] = args.as_slice() else {
+                bot.send_message(chat_id, usage).await?;
+                return Ok(());
+            };
+            let until = if duration.eq_ignore_ascii_case("off") {
+                None
+            } else {
+                match host_state::parse_duration(duration) {
+                    Some(duration) if duration > chrono::Duration::zero() => Some(chrono::Utc::now() + duration),
+                    _ => {
+                        bot.send_message(chat_id, usage).await?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            let reply = {
+                let mut app_state_guard = app_state.lock().await;
+                match app_state_guard.hosts.get_mut(*host).map(|host| &mut host.state) {
+                    None => format!("Host '{}' not found.", host),
+                    Some(state) => {
+                        state.snoozed_until = until;
+                        host_state::save_state(
+                            &app_state_guard.state_path,
+                            &app_state_guard.hosts
+                        );
+                        match until {
+                            Some(until) => {
+                                info!("{} snoozed until {} by Chat ID: {}", host, until, chat_id);
+                                format!(
+                                    "Notifications of '{}' snoozed until {}. Checks keep running.",
+                                    host,
+                                    messages::format_timestamp(until)
+                                )
+                            }
+                            None => {
+                                info!("Snooze of {} ended by Chat ID: {}", host, chat_id);
+                                format!("Notifications of '{}' are no longer snoozed.", host)
+                            }
+                        }
+                    }
+                }
+            };
+            bot.send_message(chat_id, reply).await?;
+
+            return Ok(());
+        }
+        BotCommand::Latency(args) => {
+            let hosts = {
+                let app_state_guard = app_state.lock().await;
+                app_state_guard.hosts.clone()
+            };
+            let reply = match args.split_whitespace().next() {
+                Some(host) =>
+                    match hosts.get(host) {
+                        Some(Host { state, .. }) => latency_line(host, state),
+                        None => format!("Host '{}' not found.", host),
+                    }
+                None => {
+                    let mut lines = hosts
+                        .iter()
+                        .map(|(address, host)| latency_line(address, &host.state))
+                        .collect::<Vec<_>>();
+                    lines.sort();
+                    format!("Latency:\n{}", lines.join("\n"))
+                }
+            };
+            send_chunked(&bot, chat_id, &reply).await?;
+
+            return Ok(());
+        }
+        BotCommand::History(args) => {
+            let Some(host) = args.split_whitespace().next() else {
+                bot.send_message(chat_id, "Usage: /history <host>").await?;
+                return Ok(());
+            };
+            let reply = match app_state.lock().await.hosts.get(host) {
+                Some(Host { entry, state }) => history_message(&entry.display_name(), state),
+                None => format!("Host '{}' not found.", host),
+            };
+            send_chunked(&bot, chat_id, &reply).await?;
+        }
+        BotCommand::Scanports(args) => {
+            let Some(host) = args.split_whitespace().next() else {
+                bot.send_message(chat_id, "Usage: /scanports <host>").await?;
+                return Ok(());
+            };
+            if host.starts_with('-') {
+                bot.send_message(chat_id, "Host must not start with '-'").await?;
+                return Ok(());
+            }
+            if let Some(wait) = scan_cooldown(&bot_state, &app_state, chat_id, "/scanports").await {
+                bot.send_message(chat_id, format!("Please wait {}s before scanning again", wait)).await?;
+                return Ok(());
+            }
+            bot.send_message(chat_id, format!("Scanning services on {}, this can take a while.", host)).await?;
+
+            let scan_start = Instant::now();
+            let privileges = bot_state.lock().await.config.scan_privileges;
+            let runner = app_state.lock().await.runner.clone();
+            let result = nmap::scan(&runner, &nmap::SERVICE_SCAN_ARGS, host, privileges).await.unwrap_or_else(|e| e);
+            let scan_time = scan_start.elapsed().as_secs_f64();
+            info!("Service scan of {} -> {}", host, result);
+
+            let blocks = [
+                messages::code_block(&format!("*{}*", escape_md_v2(host)), &result),
+                format!("_{}_", escape_md_v2(&format!("Nmap scan finnished in {scan_time:.2} seconds"))),
+            ];
+            send_chunked_md(&bot, chat_id, &blocks).await?;
+
+            return Ok(());
+        }
+        BotCommand::Stats => {
+            let summary = app_state.lock().await.scan_stats.summary();
+            bot.send_message(chat_id, summary).await?;
+        }
+        BotCommand::Debug(args) => {
+            let Some(host) = args.split_whitespace().next() else {
+                bot.send_message(chat_id, "Usage: /debug <host>").await?;
+                return Ok(());
+            };
+            let entry = app_state.lock().await.hosts.get(host).map(|host| host.entry.clone());
+            let reply = match entry {
+                Some(entry) => {
+                    let bot_state_guard = bot_state.lock().await;
+                    let interval = bot_state_guard.host_interval(entry.interval);
+                    debug_message(&entry, &bot_state_guard.config, interval)
+                }
+                None => format!("Host '{}' not found.", host),
+            };
+            send_chunked(&bot, chat_id, &reply).await?;
+        }
+        BotCommand::Selftest => {
+            let ping_args = bot_state.lock().await.config.ping_args.clone();
+            let runner = app_state.lock().await.runner.clone();
+            let results = selftest::run(&runner, &ping_args).await;
+            selftest::log(&results);
+            bot.send_message(chat_id, format!("Self test:\n{}", selftest::report(&results))).await?;
+        }
+        BotCommand::Test => {
+            // simulate a host going offline without touching app_state.hosts
+            let fake_stdout = format!(
+                "PING {host} ({ip}) 56(84) bytes of data.\n\n--- {host} ping statistics ---\n3 packets transmitted, 0 received, 100% packet loss, time 2003ms\n",
+                host = TEST_HOST,
+                ip = TEST_HOST_IP
+            );
+            let bot_config = {
+                let bot_state_guard = bot_state.lock().await;
+                bot_state_guard.config.clone()
+            };
+            let notification = Notification::new(
+                Severity::Critical,
+                messages::offline_message(&bot_config.templates, TEST_HOST, &fake_stdout)
+            );
+            send_chunked(
+                &bot,
+                chat_id,
+                &bot_config.severity_prefixes.apply(&notification)
+            ).await?;
+            info!("Sent test notification to Chat ID: {}", chat_id);
+
+            return Ok(());
+        }
+        BotCommand::Config(args) => {
+            let args = config_args(args);
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            if !args.is_empty() {
+                match args[0] {
+                    "edit" => {
+                        if args.get(1..3).is_some() {
+                            let value = args[2];
+                            match args[1] {
+                                "ping_interval" => {
+                                    let reply = set_ping_interval(&bot_state, value).await;
+                                    bot.send_message(chat_id, reply).await?;
+                                }
+                                "ping_args" => {
+                                    let mut bot_state_guard = bot_state.lock().await;
+                                    // parse arguments from message
+                                    let joined = args[2..].join(" ");
+                                    let value = joined.trim_matches('"');
+                                    let ping_args: Vec<String> = value
+                                        .split_whitespace()
+                                        .map(|s| s.to_string())
+                                        .collect();
+
+                                    debug!("new ping args : {:?}", &ping_args);
+                                    // test ping args the way the checks run them
+                                    let runner = app_state.lock().await.runner.clone();
+                                    match checks::ping(&runner, &ping_args, "127.0.0.1").await {
+                                        Ok(result) if result.status == CheckStatus::Up => {
+                                            let ping_args_clone = ping_args.clone();
+                                            bot_state_guard.config.ping_args = ping_args;
+                                            // write new config to file
+                                            let saved = save_config(&bot_state_guard.config).await;
+                                            let reply = format!("Ping arguments set : {:?}", &ping_args_clone);
+                                            bot.send_message(chat_id, saved_reply(reply, saved)).await?;
+                                        }
+                                        Ok(result) => {
+                                            bot.send_message(
+                                                chat_id,
+                                                format!("Invalid arguments ->{:?}", result.output.trim_end())
+                                            ).await?;
+                                        }
+                                        Err(e) => {
+                                            error!("Unable to run ping => {}", e);
+                                            bot.send_message(chat_id, format!("Unable to run ping: {}", e)).await?;
+                                        }
+                                    }
+                                }
+                                _ => {
+                                    bot.send_message(chat_id, "Invalid arguments").await?;
+                                }
+                            }
+
+                            debug!("edit_args: {:?}", args);
+                        } else {
+                            bot.send_message(chat_id, "Not enought arguments").await?;
+                        }
+                    }
+                    "list" => {
+                        bot.send_message(chat_id, config_list_message(&bot_state).await).await?;
+                    }
+                    "reset" => {
+                        bot.send_message(
+                            chat_id,
+                            "Reset config.toml to the defaults? Every setting is lost, including alert chats, tokens and the [email] section. Reply yes to reset, anything else keeps the config."
+                        ).await?;
+                        if let Err(e) = dialogue.update(DialogueState::ConfirmConfigReset).await {
+                            info!("Dialogue update error: {}", e);
+                        }
+                    }
+                    "validate" => {
+                        // the file on disk, so hand edits are checked before a restart
+                        let reply = match fs::read_to_string(CONFIG_PATH).await {
+                            Ok(content) =>
+                                match validate_config(&content) {
+                                    Ok((_, issues)) => issues.report(),
+                                    Err(e) => format!("config.toml can't be loaded: {}", e),
+                                }
+                            Err(e) => format!("Unable to read config.toml: {}", e),
+                        };
+                        send_chunked(&bot, chat_id, &reply).await?;
+                    }
+                    _ => {
+                        bot.send_message(chat_id, "Invalid input").await?;
+                    }
+                }
+            } else {
+                let buttons = vec![
+                    vec![InlineKeyboardButton::callback("Show config", "config:list")],
+                    CONFIG_INTERVAL_PRESETS.iter()
+                        .map(|seconds| {
+                            InlineKeyboardButton::callback(
+                                format!("Interval {}s", seconds),
+                                format!("config:ping_interval:{}", seconds)
+                            )
+                        })
+                        .collect()
+                ];
+                bot.send_message(
+                    chat_id,
+                    "/config list     - Show current config \n /config edit <field> <value>     - Update config field \n /config reset     - Restore the default config"
+                )
+                    .reply_markup(InlineKeyboardMarkup::new(buttons)).await?;
+            }
+
+            return Ok(());
+        }
+        BotCommand::Help => {
+            bot.send_message(chat_id, BotCommand::descriptions().to_string()).await?;
+        }
+        BotCommand::Cancel => {
+            // pending prompts are cancelled in dialogue_handler
+            bot.send_message(chat_id, "Nothing to cancel.").await?;
+        }
+    }
+
+    Ok(())
+}
+
+// role of an authorized chat, other chats are asked for the password
+async fn chat_role(
+    bot: &Bot,
+    chat_id: ChatId,
+    dialogue: &Dialogue<DialogueState, ErasedStorage<DialogueState>>,
+    app_state: &Arc<Mutex<AppState>>
+) -> Result<Option<Role>, RequestError> {
+    let role = app_state.lock().await.allowed_chats.get(&chat_id).copied();
+    if role.is_none() {
+        bot.send_message(chat_id, "Enter password or invite token").await?;
+        if let Err(e) = dialogue.update(DialogueState::WaitingForPassword).await {
+            info!("Dialogue update error: {}", e);
+        }
+    }
+    Ok(role)
+}
+
+async fn dialogue_handler(
+    bot: Bot,
+    msg: Message,
+    me: Me,
+    dialogue: Dialogue<DialogueState, ErasedStorage<DialogueState>>,
+    bot_state: Arc<Mutex<BotState>>,
+    app_state: Arc<Mutex<AppState>>
+) -> Result<(), RequestError> {
+    let chat_id = msg.chat.id;
+    let state = match dialogue.get().await {
+        Ok(state) => state.unwrap_or(DialogueState::Default),
+        Err(e) => {
+            info!("Dialogue error: {}", e);
+            DialogueState::Default
+        }
+    };
+    // saved dialogues outlive allowed_chats, a host prompt from before a restart needs the password again
+    let state = match state {
+        | DialogueState::WaitingForHostAdd
+        | DialogueState::WaitingForHostRemove
+        | DialogueState::ConfirmHostRemove { .. }
+        | DialogueState::WaitingForImport { .. }
+        | DialogueState::ConfirmConfigReset if
+            app_state.lock().await.allowed_chats.get(&chat_id) != Some(&Role::Admin)
+        => {
+            if let Err(e) = dialogue.update(DialogueState::Default).await {
+                info!("Dialogue update error: {}", e);
+            }
+            DialogueState::Default
+        }
+        state => state,
+    };
+    if let DialogueState::WaitingForImport { replace } = state && let Some(document) = msg.document() {
+        let max_hosts = bot_state.lock().await.config.max_hosts;
+        let reply = import_hosts(&bot, &app_state, document, replace, max_hosts).await;
+        send_chunked(&bot, chat_id, &reply).await?;
+        if let Err(e) = dialogue.update(DialogueState::Default).await {
+            info!("Dialogue update error: {}", e);
+        }
+        return Ok(());
+    }
+
+    let text = match msg.text() {
+        Some(text) => text,
+        // stickers, photos etc. can't answer a prompt, keep waiting for text
+        None if matches!(state, DialogueState::WaitingForImport { .. }) => {
+            bot.send_message(chat_id, "Please send the hosts file as a document, or /cancel.").await?;
+            return Ok(());
+        }
+        None if !matches!(state, DialogueState::Default) => {
+            bot.send_message(chat_id, "Please send text").await?;
+            return Ok(());
+        }
+        None => "",
+    };
+
+    // checked before any waiting state treats the text as its input
+    if
+        !matches!(state, DialogueState::Default) &&
+        matches!(BotCommand::parse(text, me.username()), Ok(BotCommand::Cancel))
+    {
+        if let Err(e) = dialogue.update(DialogueState::Default).await {
+            info!("Dialogue update error: {}", e);
+        }
+        bot.send_message(chat_id, "Cancelled").await?;
+        return Ok(());
+    }
+
+    match state {
+        DialogueState::Default => {
+            // valid commands go to command_handler, this is any other text
+            if
+                chat_role(&bot, chat_id, &dialogue, &app_state).await?.is_some() &&
+                text.starts_with('/') &&
+                let Err(ParseError::UnknownCommand(command)) = BotCommand::parse(text, me.username())
+            {
+                bot.send_message(chat_id, format!("Unknown command {}, see /help", command)).await?;
+            }
+        }
+        DialogueState::WaitingForPassword => {
+            let (password, viewer_password) = {
+                let app_state_guard = app_state.lock().await;
+                (app_state_guard.password.clone(), app_state_guard.viewer_password.clone())
+            };
+
+            let role = if text == password {
+                Some(Role::Admin)
+            } else if viewer_password.is_some_and(|viewer_password| text == viewer_password) {
+                Some(Role::Viewer)
+            } else {
+                redeem_invite(&app_state, text).await
+            };
+
+            if let Some(role) = role {
+                {
+                    let mut app_state_guard = app_state.lock().await;
+                    app_state_guard.allowed_chats.insert(chat_id, role);
+                }
+                let reply = match role {
+                    Role::Admin =>
+                        "Password accepted! You can now use /start, /stop, /status, /hosts, /add, /remove.",
+                    Role::Viewer =>
+                        "Password accepted! You have read only access to /status, /hosts, /latency and /config list.",
+                };
+                bot.send_message(chat_id, reply).await?;
+                info!("Chat ID {} authorized as {:?}", chat_id, role);
+                if let Err(e) = dialogue.update(DialogueState::Default).await {
+                    info!("Dialogue update error: {}", e);
+                }
+            } else {
+                bot.send_message(chat_id, "Incorrect password. Try again.").await?;
+            }
+        }
+
+        DialogueState::WaitingForHostAdd => {
+            let entry = match HostEntry::parse(text) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    bot.send_message(chat_id, format!("Invalid host: {}", e)).await?;
+                    if let Err(e) = dialogue.update(DialogueState::Default).await {
+                        info!("Dialogue update error: {}", e);
+                    }
+                    return Ok(());
+                }
+            };
+            let name = entry.display_name();
+            let reply = match add_host(&bot_state, &app_state, entry).await {
+                Ok(result) => {
+                    let templates = bot_state.lock().await.config.templates.clone();
+                    messages::host_added_message(&templates, &name, &result)
+                }
+                Err(reason) => reason.to_string(),
+            };
+            bot.send_message(chat_id, reply).await?;
+
+            if let Err(e) = dialogue.update(DialogueState::Default).await {
+                info!("Dialogue update error: {}", e);
+            }
+        }
+
+        DialogueState::WaitingForImport { .. } => {
+            // documents are handled above
+            bot.send_message(chat_id, "Please send the hosts file as a document, or /cancel.").await?;
+        }
+
+        DialogueState::WaitingForHostRemove => {
+            let host_remove = text;
+
+            let next_state = if app_state.lock().await.hosts.contains_key(host_remove) {
+                bot.send_message(chat_id, remove_confirmation(host_remove)).await?;
+                DialogueState::ConfirmHostRemove { host: host_remove.to_string() }
+            } else {
+                bot.send_message(chat_id, format!("Host '{}' not found.", host_remove)).await?;
+                DialogueState::Default
+            };
+
+            if let Err(e) = dialogue.update(next_state).await {
+                info!("Dialogue update error: {}", e);
+            }
+        }
+
+        DialogueState::ConfirmHostRemove { host } => {
+            let reply = if !text.trim().eq_ignore_ascii_case("yes") {
+                format!("Host '{}' not removed.", host)
+            } else {
+                match remove_host(&app_state, &host).await {
+                    Ok(true) => format!("Host '{}' removed.", host),
+                    Ok(false) => format!("Host '{}' not found.", host),
+                    Err(reason) => format!("Host '{}' not removed. {}", host, reason),
+                }
+            };
+            bot.send_message(chat_id, reply).await?;
+
+            if let Err(e) = dialogue.update(DialogueState::Default).await {
+                info!("Dialogue update error: {}", e);
+            }
+        }
+
+        DialogueState::ConfirmConfigReset => {
+            let reply = if text.trim().eq_ignore_ascii_case("yes") {
+                reset_config(&bot_state).await
+            } else {
+                "Config not reset.".to_string()
+            };
+            bot.send_message(chat_id, reply).await?;
+
+            if let Err(e) = dialogue.update(DialogueState::Default).await {
+                info!("Dialogue update error: {}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// handles presses of inline keyboard buttons sent by /remove and /config
+async fn callback_handler(
+    bot: Bot,
+    q: CallbackQuery,
+    bot_state: Arc<Mutex<BotState>>,
+    app_state: Arc<Mutex<AppState>>,
+    dialogue_storage: Arc<ErasedStorage<DialogueState>>
+) -> Result<(), RequestError> {
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    let (Some(data), Some(message)) = (q.data.as_deref(), q.regular_message()) else {
+        return Ok(());
+    };
+    let chat_id = message.chat.id;
+
+    let role = {
+        let app_state_guard = app_state.lock().await;
+        app_state_guard.allowed_chats.get(&chat_id).copied()
+    };
+    let Some(role) = role else {
+        return Ok(());
+    };
+    // every button except showing the config changes state
+    if role != Role::Admin && data != "config:list" {
+        bot.send_message(chat_id, "This command requires full access.").await?;
+        return Ok(());
+    }
+
+    let reply = match data.split_once(':') {
+        Some(("remove", host)) => {
+            // a pressed button answers the pending /remove prompt, the removal still has to be confirmed
+            if app_state.lock().await.hosts.contains_key(host) {
+                let dialogue = Dialogue::new(dialogue_storage, chat_id);
+                if let Err(e) = dialogue.update(DialogueState::ConfirmHostRemove { host: host.to_string() }).await {
+                    info!("Dialogue update error: {}", e);
+                }
+                remove_confirmation(host)
+            } else {
+                format!("Host '{}' not found.", host)
+            }
+        }
+        Some(("add", host)) => {
+            // same checks as typing the host after /add
+            match HostEntry::parse(host) {
+                Ok(entry) => {
+                    let name = entry.display_name();
+                    match add_host(&bot_state, &app_state, entry).await {
+                        Ok(result) => {
+                            let templates = bot_state.lock().await.config.templates.clone();
+                            messages::host_added_message(&templates, &name, &result)
+                        }
+                        Err(reason) => reason.to_string(),
+                    }
+                }
+                Err(e) => format!("Invalid host: {}", e),
+            }
+        }
+        Some(("addfrom", "arp")) => {
+            // read again, the table may have changed since the buttons were sent
+            match arp_candidates(&app_state).await {
+                Ok(candidates) => {
+                    bot.send_message(chat_id, format!("Checking and adding {} hosts.", candidates.len())).await?;
+                    let mut added = Vec::new();
+                    let mut refused = Vec::new();
+                    for address in candidates {
+                        let entry = match HostEntry::parse(&address) {
+                            Ok(entry) => entry,
+                            Err(e) => {
+                                refused.push(format!("{}: {}", address, e));
+                                continue;
+                            }
+                        };
+                        match add_host(&bot_state, &app_state, entry).await {
+                            Ok(_) => added.push(address),
+                            Err(reason) => refused.push(format!("{}: {}", address, reason)),
+                        }
+                    }
+                    let mut reply = format!("Added {} hosts from the neighbor table.", added.len());
+                    if !refused.is_empty() {
+                        reply.push_str(&format!("\nNot added:\n{}", refused.join("\n")));
+                    }
+                    reply
+                }
+                Err(e) => format!("Unable to read the neighbor table: {}", e),
+            }
+        }
+        Some(("config", "list")) => config_list_message(&bot_state).await,
+        Some(("config", field_value)) =>
+            match field_value.split_once(':') {
+                Some(("ping_interval", value)) => set_ping_interval(&bot_state, value).await,
+                _ => "Invalid input".to_string(),
+            }
+        _ => "Invalid input".to_string(),
+    };
+    send_chunked(&bot, chat_id, &reply).await?;
+
+    Ok(())
+}
+
+// addresses from the neighbor table that aren't monitored yet
+async fn arp_candidates(app_state: &Arc<Mutex<AppState>>) -> Result<Vec<String>, String> {
+    let runner = app_state.lock().await.runner.clone();
+    let addresses = neighbors::arp_table(&runner).await?;
+    let app_state_guard = app_state.lock().await;
+    Ok(
+        addresses
+            .into_iter()
+            .map(|address| address.to_string())
+            .filter(|address| !app_state_guard.hosts.contains_key(address))
+            .collect()
+    )
+}
+
+fn remove_confirmation(host: &str) -> String {
+    format!("Remove host '{}'? Reply yes to remove it, anything else keeps it.", host)
+}
+
+// end the monitoring loop, Err is why it could not be stopped
+async fn stop_monitoring(bot_state: &Arc<Mutex<BotState>>) -> Result<(), &'static str> {
+    let mut bot_state_guard = bot_state.lock().await;
+    bot_state_guard.paused = false;
+    let tx = bot_state_guard.task.take().ok_or("No task is running.")?;
+    tx.send(()).map_err(|_| "Failed to stop task.")?;
+    info!("Task stopped for Chat ID: {:?}", bot_state_guard.chat_id);
+    Ok(())
+}
+
+// delivers the notifications queued by the monitoring loop of chat_id in order,
+// ends when the loop stops and drops its end of the channel
+async fn notification_sender(
+    bot: Bot,
+    bot_state: Arc<Mutex<BotState>>,
+    app_state: Arc<Mutex<AppState>>,
+    chat_id: ChatId,
+    mut notifications: mpsc::UnboundedReceiver<Notification>
+) {
+    let http_client = reqwest::Client::new();
+    while let Some(notification) = notifications.recv().await {
+        // read for every notification so /setalertchat and /prefs apply to queued ones
+        let bot_config = bot_state.lock().await.config.clone();
+        let chats = bot_config.notification_chats(chat_id, notification.severity);
+        let failed = notify(&bot, &http_client, &bot_config, &chats, &notification).await;
+        let Some(gone) = failed
+            .into_iter()
+            .filter(|(_, e)| is_chat_gone(e))
+            .map(|(gone, _)| gone)
+            .min_by_key(|gone| *gone != chat_id)
+        else {
+            continue;
+        };
+        let mut bot_state_guard = bot_state.lock().await;
+        if gone != chat_id && bot_state_guard.config.alert_chat_id == Some(gone.0) {
+            // fall back to the controlling chat, this alert is lost
+            warn!("Alert chat {} is gone, sending alerts to Chat ID {}", gone, chat_id);
+            bot_state_guard.config.alert_chat_id = None;
+            // a failed write is logged by save_config, there is no chat to tell
+            let _ = save_config(&bot_state_guard.config).await;
+        } else if gone != chat_id {
+            // severity_chats is only changed by editing config.toml
+            warn!("Chat ID {} in severity_chats is gone or blocked the bot", gone);
+        } else {
+            warn!("Chat ID {} is gone or blocked the bot, stopping its monitoring task", chat_id);
+            if bot_state_guard.config.last_chat_id == Some(chat_id.0) {
+                bot_state_guard.config.last_chat_id = None;
+                let _ = save_config(&bot_state_guard.config).await;
+            }
+            // the task may already have been stopped and started again for another chat
+            let current = bot_state_guard.chat_id == Some(chat_id);
+            drop(bot_state_guard);
+            app_state.lock().await.allowed_chats.remove(&chat_id);
+            if current && let Err(e) = stop_monitoring(&bot_state).await {
+                warn!("Failed to stop monitoring for Chat ID {} => {}", chat_id, e);
+            }
+            break;
+        }
+    }
+}
+
+// spawn the monitoring loop sending notifications to chat_id, returns false if it is already running
+async fn start_monitoring(
+    bot: &Bot,
+    bot_state: &Arc<Mutex<BotState>>,
+    app_state: &Arc<Mutex<AppState>>,
+    chat_id: ChatId
+) -> bool {
+    let mut bot_state_guard = bot_state.lock().await;
+    if bot_state_guard.task.is_some() {
+        return false;
+    }
+
+    bot_state_guard.chat_id = Some(chat_id);
+    // remembered so autostart can resume monitoring for this chat after a restart
+    if bot_state_guard.config.last_chat_id != Some(chat_id.0) {
+        bot_state_guard.config.last_chat_id = Some(chat_id.0);
+        // a failed write is logged by save_config, monitoring starts anyway but isn't resumed after a restart
+        let _ = save_config(&bot_state_guard.config).await;
+    }
+    info!("Host monitoring task started. \nChat ID: {}", chat_id);
+
+    let (tx, rx) = oneshot::channel();
+    bot_state_guard.task = Some(tx);
+    bot_state_guard.last_cycle = Some(Instant::now());
+    let bot_config = bot_state_guard.config.clone();
+    let app_state_clone = Arc::clone(app_state);
+    let bot_state_clone = Arc::clone(bot_state);
+
+    let reminder_interval = chrono::Duration::minutes(bot_config.reminder_interval as i64);
+    let watcher_bot = bot.clone();
+    let watcher_bot_state = Arc::clone(bot_state);
+
+    // the loop only queues notifications, a slow telegram api must not delay the checks
+    let (notifications_tx, notifications_rx) = mpsc::unbounded_channel();
+    tokio::spawn(
+        notification_sender(bot.clone(), Arc::clone(bot_state), Arc::clone(app_state), chat_id, notifications_rx)
+    );
+
+    let task = tokio::spawn(async move {
+        let mut rx = rx;
+        // nothing is known until the first cycle of this task checked a host
+        for host in app_state_clone.lock().await.hosts.values_mut() {
+            host.state.pending = true;
+        }
+        // built once per task, like the rest of bot_config
+        let http_clients = checks::HttpClients::new(&bot_config.tls_profiles);
+        let (interval_changed, check_now) = {
+            let bot_state_guard = bot_state_clone.lock().await;
+            (Arc::clone(&bot_state_guard.interval_changed), Arc::clone(&bot_state_guard.check_now))
+        };
+        let (runner, hosts_path) = {
+            let app_state_guard = app_state_clone.lock().await;
+            (app_state_guard.runner.clone(), app_state_guard.hosts_path.clone())
+        };
+        let mut resource_monitor = bot_config.resources
+            .enabled()
+            .then(|| resources::ResourceMonitor::new(&hosts_path));
+        // when each host is next due for a check, hosts without an entry are due now
+        let mut next_due: HashMap<String, Instant> = HashMap::new();
+        // hosts whose open ports were scanned for recovery_port_check, so each is scanned once per task
+        let mut port_baselines = HashSet::new();
+        // a cycle took longer than the interval, warned once until the loop keeps up again
+        let mut falling_behind = false;
+        // the network or dns may not be ready right after a reboot, the first checks then only record state
+        let grace_end = Instant::now() + Duration::from_secs(bot_config.startup_grace);
+        // outages that began during the grace, alerted when it ends for hosts that are still offline
+        let mut held_offline: Vec<(String, Notification)> = Vec::new();
+        let mut deduplicator = messages::Deduplicator::new(Duration::from_secs(bot_config.dedup_window));
+        // spreads out the checks of bots that were restarted together
+        let mut jitter = random_jitter(bot_config.startup_jitter);
+        loop {
+            // read every cycle so /config edit and /fastcheck apply to the running task,
+            // the loop wakes for the host with the shortest interval
+            let host_intervals = {
+                let app_state_guard = app_state_clone.lock().await;
+                app_state_guard.hosts
+                    .values()
+                    .map(|host| host.entry.interval)
+                    .collect::<Vec<_>>()
+            };
+            let ping_interval = {
+                let bot_state_guard = bot_state_clone.lock().await;
+                host_intervals
+                    .into_iter()
+                    .map(|interval| bot_state_guard.host_interval(interval))
+                    .fold(bot_state_guard.ping_interval(), u64::min)
+            };
+            tokio::select! {
+                _ = &mut rx => {
+                    info!("Task for Chat ID {} stopped", chat_id);
+                    break;
+                }
+                _ = interval_changed.notified() => {
+                    continue;
+                }
+                _ = check_now.notified() => {
+                    // every host is due, the wait for the next cycle starts over afterwards
+                    next_due.clear();
+                }
+                _ = sleep(Duration::from_secs(ping_interval) + jitter) => {}
+            }
+            jitter = random_jitter(bot_state_clone.lock().await.config.cycle_jitter);
+            {
+                let mut bot_state_guard = bot_state_clone.lock().await;
+                if bot_state_guard.paused {
+                    // the loop is alive, only the checks are skipped
+                    bot_state_guard.last_cycle = Some(Instant::now());
+                    continue;
+                }
+            }
+            // only the addresses are copied, each host is read from the map when its turn comes
+            // so changes made during the cycle are seen and its state is never written back from a stale copy
+            let addresses = {
+                let app_state_guard = app_state_clone.lock().await;
+                next_due.retain(|address, _| app_state_guard.hosts.contains_key(address));
+                app_state_guard.hosts.keys().cloned().collect::<Vec<_>>()
+            };
+            // notifications are sent once all hosts are checked so hosts going offline together are batched
+            let mut went_offline = Vec::new();
+            let mut cycle_notifications = Vec::new();
+            let cycle_start = Instant::now();
+            for address in addresses {
+                // a little slack so a host isn't pushed back a whole tick by timer jitter
+                if next_due.get(&address).is_some_and(|due| *due > cycle_start + Duration::from_secs(1)) {
+                    continue;
+                }
+                // removed since the cycle started, or edited eg. with /label or /critical
+                let Some(entry) = app_state_clone.lock().await.hosts.get(&address).map(|host| host.entry.clone()) else {
+                    continue;
+                };
+                let interval = bot_state_clone.lock().await.host_interval(entry.interval);
+                next_due.insert(address.clone(), cycle_start + Duration::from_secs(interval));
+
+                // a subnet also reports each of its addresses
+                let (result, members) = match &entry.method {
+                    CheckMethod::Subnet { network } => {
+                        let members = checks::check_subnet(&runner, &entry, &bot_config).await;
+                        (checks::subnet_outcome(network, &members), members)
+                    }
+                    _ => (checks::check_host(&runner, &entry, &bot_config, &http_clients).await, Vec::new()),
+                };
+                if result.status == CheckStatus::Error {
+                    error!("Unable to check {} => {}", address, result.output);
+                    continue;
+                }
+
+                // notifications name the host by its label when it has one
+                let name = entry.display_name();
+                // offline is normal during the host's expected_down hours
+                let expected_down = entry.expected_down
+                    .as_ref()
+                    .is_some_and(|schedule| schedule.is_active(chrono::Local::now()));
+
+                // update state of the host in place, it may have been removed during the check
+                let templates = &bot_config.templates;
+                let mut notifications = Vec::new();
+                let mut offline_notification = None;
+                // position of the recovery notification, a port check may add to it
+                let mut recovery = None;
+                // went offline or recovered in this check, counted by flap detection
+                let changed;
+                // read from the state as it is after this check, not as it was when the cycle started
+                let snoozed;
+                let ports_known;
+                {
+                    let mut app_state_guard = app_state_clone.lock().await;
+                    let Some(Host { state, .. }) = app_state_guard.hosts.get_mut(&address) else {
+                        continue;
+                    };
+                    if result.status == CheckStatus::Up {
+                        // nothing was sent when an expected outage began, so neither is a recovery
+                        let expected_outage = state.expected_outage;
+                        let recovered = state.mark_online();
+                        changed = recovered.is_some();
+                        if let Some(downtime) = recovered && !expected_outage {
+                            recovery = Some(notifications.len());
+                            notifications.push(
+                                Notification::new(
+                                    Severity::Info,
+                                    messages::recovered_message(
+                                        templates,
+                                        &name,
+                                        downtime,
+                                        result.latency
+                                    )
+                                ).with_event(&address, "online")
+                            );
+                        }
+                        if let Some(latency) = result.latency {
+                            let threshold = bot_config.latency_threshold_ms;
+                            match state.record_latency(latency, threshold) {
+                                Some(LatencyChange::Degraded) => {
+                                    notifications.push(
+                                        Notification::new(
+                                            Severity::Warning,
+                                            messages::degraded_message(
+                                                templates,
+                                                &name,
+                                                latency,
+                                                threshold
+                                            )
+                                        ).with_event(&address, "degraded")
+                                    );
+                                }
+                                Some(LatencyChange::Normal) => {
+                                    notifications.push(
+                                        Notification::new(
+                                            Severity::Info,
+                                            messages::latency_normal_message(
+                                                templates,
+                                                &name,
+                                                latency
+                                            )
+                                        ).with_event(&address, "latency_normal")
+                                    );
+                                }
+                                None => {}
+                            }
+                        }
+                        if let Some(loss) = result.packet_loss {
+                            let threshold = bot_config.loss_threshold;
+                            match state.record_packet_loss(loss, threshold) {
+                                Some(LatencyChange::Degraded) => {
+                                    notifications.push(
+                                        Notification::new(
+                                            Severity::Warning,
+                                            messages::packet_loss_message(templates, &name, loss, threshold)
+                                        ).with_event(&address, "packet_loss")
+                                    );
+                                }
+                                Some(LatencyChange::Normal) => {
+                                    notifications.push(
+                                        Notification::new(
+                                            Severity::Info,
+                                            messages::packet_loss_normal_message(templates, &name, loss)
+                                        ).with_event(&address, "packet_loss_normal")
+                                    );
+                                }
+                                None => {}
+                            }
+                        }
+                    } else {
+                        let went_offline = state.mark_offline();
+                        changed = went_offline;
+                        if went_offline && expected_down {
+                            info!("{} is offline during its expected_down hours", address);
+                            state.expected_outage = true;
+                        } else if went_offline || (state.expected_outage && !expected_down) {
+                            // an expected outage that outlasts the expected_down hours is alerted like a new one
+                            if state.expected_outage {
+                                state.expected_outage = false;
+                                state.last_reminder = Some(chrono::Utc::now());
+                            }
+                            let (text, status) = if result.status == CheckStatus::Unhealthy {
+                                (messages::unhealthy_message(templates, &name, &result.output), "unhealthy")
+                            } else {
+                                (messages::offline_message(templates, &name, &result.output), "offline")
+                            };
+                            offline_notification = Some(
+                                Notification::new(Severity::Critical, with_note(text, &entry)).with_event(
+                                    &address,
+                                    status
+                                )
+                            );
+                        } else if
+                            !expected_down &&
+                            let Some(downtime) = state.reminder_due(reminder_interval)
+                        {
+                            notifications.push(
+                                Notification::new(
+                                    Severity::Critical,
+                                    with_note(
+                                        messages::still_offline_message(templates, &name, downtime),
+                                        &entry
+                                    )
+                                ).with_event(&address, "still_offline")
+                            );
+                        }
+                    }
+                    // a subnet that is down as a whole is alerted above, its addresses are compared once it is back
+                    if !members.is_empty() && result.status == CheckStatus::Up {
+                        let members = members
+                            .iter()
+                            .filter(|(_, status)| *status != CheckStatus::Error)
+                            .map(|(member, status)| (member.clone(), *status == CheckStatus::Up))
+                            .collect::<Vec<_>>();
+                        let (went_down, came_back) = state.record_members(&members);
+                        // addresses are still recorded during the expected_down hours
+                        if !expected_down {
+                            for member in went_down {
+                                let member_name = format!("{} in {}", member, name);
+                                let output = format!("{} stopped answering, {}", member, result.output);
+                                notifications.push(
+                                    Notification::new(
+                                        Severity::Critical,
+                                        with_note(messages::offline_message(templates, &member_name, &output), &entry)
+                                    ).with_event(&member, "offline")
+                                );
+                            }
+                            for (member, downtime) in came_back {
+                                let member_name = format!("{} in {}", member, name);
+                                notifications.push(
+                                    Notification::new(
+                                        Severity::Info,
+                                        messages::recovered_message(templates, &member_name, downtime, None)
+                                    ).with_event(&member, "online")
+                                );
+                            }
+                        }
+                    }
+                    if bot_config.flap_threshold > 0 {
+                        let window = chrono::Duration::seconds(bot_config.flap_window as i64);
+                        let started = changed && state.record_transition(bot_config.flap_threshold, window);
+                        if started || state.flapping {
+                            // the flapping alert stands in for every other alert of the host
+                            notifications.clear();
+                            offline_notification = None;
+                            recovery = None;
+                        }
+                        if started {
+                            warn!("{} is flapping", address);
+                            notifications.push(
+                                Notification::new(
+                                    Severity::Warning,
+                                    messages::flapping_message(templates, &name, state.transitions.len(), window)
+                                ).with_event(&address, "flapping")
+                            );
+                        } else if state.flap_ended(window) {
+                            info!("{} stopped flapping", address);
+                            notifications.push(
+                                Notification::new(
+                                    if state.online { Severity::Info } else { Severity::Critical },
+                                    messages::flap_ended_message(templates, &name, state.online)
+                                ).with_event(&address, if state.online { "online" } else { "offline" })
+                            );
+                        }
+                    }
+                    snoozed = state.snoozed();
+                    ports_known = state.open_ports.is_some();
+                    state.prune(&bot_config.retention);
+                }
+
+                if bot_config.recovery_port_check && result.status == CheckStatus::Up {
+                    let target = entry.scan_target();
+                    let privileges = bot_config.scan_privileges;
+                    if let Some(index) = recovery {
+                        // a service that didn't come back is worth more than an info
+                        let missing = refresh_open_ports(&app_state_clone, &address, &target, privileges).await;
+                        if !missing.is_empty() {
+                            let notification = &mut notifications[index];
+                            notification.severity = Severity::Warning;
+                            notification.text = messages::missing_ports_note(&notification.text, &missing);
+                        }
+                        port_baselines.insert(address.clone());
+                    } else if !ports_known && port_baselines.insert(address.clone()) {
+                        // ports to compare with after a future outage, scanned in the background
+                        let app_state = Arc::clone(&app_state_clone);
+                        let address = address.clone();
+                        tokio::spawn(async move {
+                            refresh_open_ports(&app_state, &address, &target, privileges).await;
+                        });
+                    }
+                }
+
+                // state is still tracked above, only the notification is muted, critical hosts always alert
+                if
+                    (!notifications.is_empty() || offline_notification.is_some()) &&
+                    !entry.critical &&
+                    maintenance::in_maintenance(
+                        &bot_config.maintenance_windows,
+                        &address,
+                        chrono::Local::now()
+                    )
+                {
+                    info!("Notification for {} muted by maintenance window", address);
+                    continue;
+                }
+                if (!notifications.is_empty() || offline_notification.is_some()) && snoozed {
+                    info!("Notification for {} muted by /snooze", address);
+                    continue;
+                }
+                let critical_host = entry.critical;
+                if let Some(mut notification) = offline_notification {
+                    notification.critical_host = critical_host;
+                    went_offline.push((name, notification));
+                }
+                cycle_notifications.extend(
+                    notifications.into_iter().map(|mut notification| {
+                        notification.critical_host = critical_host;
+                        notification
+                    })
+                );
+            }
+            // written once per cycle instead of after every host check
+            {
+                let app_state_guard = app_state_clone.lock().await;
+                host_state::save_state(&app_state_guard.state_path, &app_state_guard.hosts);
+            }
+
+            // checks run one after another, too many slow hosts stretch every cycle past the interval
+            let cycle_time = cycle_start.elapsed();
+            if cycle_time > Duration::from_secs(ping_interval) {
+                if !falling_behind {
+                    warn!("Check cycle took {:.1}s, longer than the {}s interval", cycle_time.as_secs_f64(), ping_interval);
+                    cycle_notifications.push(
+                        Notification::new(
+                            Severity::Warning,
+                            messages::falling_behind_message(cycle_time.as_secs_f64(), ping_interval)
+                        )
+                    );
+                    falling_behind = true;
+                }
+            } else if falling_behind {
+                info!("Check cycle took {:.1}s, monitoring keeps up again", cycle_time.as_secs_f64());
+                falling_behind = false;
+            }
+
+            if let Some(monitor) = &mut resource_monitor {
+                let host_name = resources::host_name();
+                for change in monitor.check(&bot_config.resources) {
+                    let notification = if change.high {
+                        warn!("{} usage {:.0}% is above {:.0}%", change.resource, change.usage, change.threshold);
+                        Notification::new(
+                            Severity::Warning,
+                            messages::resource_high_message(&host_name, change.resource, change.usage, change.threshold)
+                        )
+                    } else {
+                        Notification::new(
+                            Severity::Info,
+                            messages::resource_normal_message(&host_name, change.resource, change.usage)
+                        )
+                    };
+                    cycle_notifications.push(notification);
+                }
+            }
+
+            if Instant::now() < grace_end {
+                // subnet addresses going offline are held like hosts
+                let (members_offline, rest): (Vec<_>, Vec<_>) = cycle_notifications
+                    .into_iter()
+                    .partition(|notification| {
+                        !notification.events.is_empty() &&
+                            notification.events.iter().all(|event| event.status == "offline")
+                    });
+                cycle_notifications = rest;
+                let members_offline = members_offline
+                    .into_iter()
+                    .map(|notification| (notification.events[0].host.clone(), notification));
+                for (name, notification) in went_offline.drain(..).chain(members_offline) {
+                    // a host that went offline again only needs its latest outage
+                    held_offline.retain(|(_, held)| held.events[0].host != notification.events[0].host);
+                    held_offline.push((name, notification));
+                }
+            } else if !held_offline.is_empty() {
+                let app_state_guard = app_state_clone.lock().await;
+                for (name, notification) in held_offline.drain(..) {
+                    let still_offline = notification.events.iter().all(|event| {
+                        match app_state_guard.hosts.get(&event.host) {
+                            Some(host) => !host.state.online,
+                            None =>
+                                app_state_guard.hosts
+                                    .values()
+                                    .any(|host| host.state.subnet_down.contains_key(&event.host)),
+                        }
+                    });
+                    if still_offline {
+                        info!("{} is still offline after startup grace", name);
+                        went_offline.push((name, notification));
+                    }
+                }
+            }
+
+            // a single offline host keeps its detailed message
+            if went_offline.len() > 1 {
+                let critical_host = went_offline.iter().any(|(_, notification)| notification.critical_host);
+                let (addresses, notifications): (Vec<_>, Vec<_>) = went_offline.into_iter().unzip();
+                let mut notification = Notification::new(
+                    Severity::Critical,
+                    messages::offline_batch_message(&bot_config.templates, &addresses)
+                );
+                notification.critical_host = critical_host;
+                // webhooks still get one request per host
+                notification.events = notifications
+                    .into_iter()
+                    .flat_map(|notification| notification.events)
+                    .collect();
+                cycle_notifications.insert(0, notification);
+            } else if let Some((_, notification)) = went_offline.pop() {
+                cycle_notifications.insert(0, notification);
+            }
+
+            // read on every cycle so /silence and /quiethours apply to the running task
+            let (silenced, quiet_hours) = {
+                let bot_state_guard = bot_state_clone.lock().await;
+                (bot_state_guard.silenced, bot_state_guard.config.quiet_hours.clone())
+            };
+            if silenced {
+                let before = cycle_notifications.len();
+                cycle_notifications.retain(|notification| notification.critical_host);
+                if cycle_notifications.len() < before {
+                    info!("Silenced, dropping {} notifications", before - cycle_notifications.len());
+                }
+            }
+            if Instant::now() < grace_end && !cycle_notifications.is_empty() {
+                info!("Startup grace, dropping {} notifications", cycle_notifications.len());
+                cycle_notifications.clear();
+            }
+            // unlike /silence, quiet hours still let outages through
+            if
+                let Some(quiet_hours) = &quiet_hours &&
+                quiet_hours.is_active(chrono::Local::now())
+            {
+                let before = cycle_notifications.len();
+                cycle_notifications.retain(|notification| {
+                    notification.severity >= Severity::Critical || notification.critical_host
+                });
+                if cycle_notifications.len() < before {
+                    info!("Quiet hours, dropping {} non critical notifications", before - cycle_notifications.len());
+                }
+            }
+            let before = cycle_notifications.len();
+            cycle_notifications.retain(|notification| !deduplicator.is_duplicate(notification));
+            if cycle_notifications.len() < before {
+                warn!("Dropping {} notifications repeated within dedup_window", before - cycle_notifications.len());
+            }
+            for notification in cycle_notifications {
+                // only fails once the sender gave up on a gone chat and stopped this task
+                let _ = notifications_tx.send(notification);
+            }
+            bot_state_clone.lock().await.last_cycle = Some(Instant::now());
+        }
+        let mut bot_state_guard = bot_state_clone.lock().await;
+        bot_state_guard.task = None;
+    });
+    tokio::spawn(watchdog(bot.clone(), Arc::clone(bot_state), chat_id, task.abort_handle()));
+
+    // a panic in the loop would leave task set and /start refusing to run, clear it and tell the chat
+    tokio::spawn(async move {
+        if let Err(e) = task.await && e.is_panic() {
+            error!("Monitoring task for Chat ID {} panicked => {}", chat_id, e);
+            {
+                let mut bot_state_guard = watcher_bot_state.lock().await;
+                bot_state_guard.task = None;
+                bot_state_guard.paused = false;
+            }
+            if
+                let Err(e) = watcher_bot.send_message(
+                    chat_id,
+                    "Monitoring stopped unexpectedly, please /start again"
+                ).await
+            {
+                error!("Failed to send notification => {}", e);
+            }
+        }
+    });
+    true
+}
+
+// random delay of up to max_seconds
+fn random_jitter(max_seconds: u64) -> Duration {
+    if max_seconds == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rand::rng().random_range(0..=max_seconds * 1000))
+}
+
+// alert when the monitoring loop stops finishing cycles, eg. a check that never returns
+async fn watchdog(bot: Bot, bot_state: Arc<Mutex<BotState>>, chat_id: ChatId, task: tokio::task::AbortHandle) {
+    let http_client = reqwest::Client::new();
+    let mut stalled = false;
+    loop {
+        let ping_interval = bot_state.lock().await.ping_interval();
+        sleep(Duration::from_secs(ping_interval)).await;
+        if task.is_finished() {
+            break;
+        }
+
+        let (last_cycle, bot_config, silenced) = {
+            let bot_state_guard = bot_state.lock().await;
+            (bot_state_guard.last_cycle, bot_state_guard.config.clone(), bot_state_guard.silenced)
+        };
+        let jitter = bot_config.startup_jitter.max(bot_config.cycle_jitter);
+        let limit = Duration::from_secs(ping_interval * WATCHDOG_STALL_FACTOR + jitter).max(WATCHDOG_MIN_STALL);
+        let since = last_cycle.map(|last| last.elapsed()).unwrap_or_default();
+
+        let notification = if !stalled && since > limit {
+            stalled = true;
+            error!("Monitoring loop has not finished a cycle for {:?}", since);
+            Notification::new(
+                Severity::Critical,
+                format!("Monitoring appears stalled, no check cycle finished for {}s", since.as_secs())
+            )
+        } else if stalled && since <= limit {
+            stalled = false;
+            info!("Monitoring loop is running again");
+            Notification::new(Severity::Info, "Monitoring is running again".to_string())
+        } else {
+            continue;
+        };
+        if silenced {
+            continue;
+        }
+        // a gone chat is handled by the monitoring loop
+        let chats = bot_config.notification_chats(chat_id, notification.severity);
+        notify(&bot, &http_client, &bot_config, &chats, &notification).await;
+    }
+}
+
+// nmap scan of all hosts in parallel, sorted by address, with the scan time in seconds
+async fn scan_hosts(
+    bot_state: &Arc<Mutex<BotState>>,
+    app_state: &Arc<Mutex<AppState>>,
+    hosts: &HashMap<String, Host>
+) -> (Vec<(String, Result<String, String>)>, f64) {
+    let (privileges, scan_history) = {
+        let bot_state_guard = bot_state.lock().await;
+        (bot_state_guard.config.scan_privileges, bot_state_guard.config.retention.scan_history)
+    };
+    let runner = app_state.lock().await.runner.clone();
+    let mut handles = Vec::new();
+    // start timer for host scan
+    let scan_start = Instant::now();
+
+    for (address, host) in hosts {
+        let address = address.clone();
+        let target = host.entry.scan_target();
+        let runner = runner.clone();
+        let handle = tokio::spawn(async move {
+            let result = nmap::scan(&runner, privileges.status_args(), &target, privileges).await;
+            (address, result)
+        });
+        handles.push(handle);
+    }
+
+    let mut responses: Vec<(String, Result<String, String>)> = Vec::new();
+    for handle in handles {
+        match handle.await {
+            Ok(response) => responses.push(response),
+            Err(e) => info!("ERROR -> {}", e),
+        }
+    }
+    let scan_time = scan_start.elapsed().as_secs_f64();
+    responses.sort();
+
+    let results = responses
+        .iter()
+        .filter_map(|(address, output)| {
+            let output = output.as_ref().ok()?;
+            Some((address.clone(), nmap::HostScanResult::from_output(output)))
+        })
+        .collect::<HashMap<_, _>>();
+    let mut app_state_guard = app_state.lock().await;
+    app_state_guard.scan_stats.record(scan_time, hosts.len(), scan_history);
+    // also the ports recovery_port_check compares with after an outage
+    for (address, result) in &results {
+        if result.up && let Some(host) = app_state_guard.hosts.get_mut(address) {
+            host.state.open_ports = Some(result.open_ports.clone());
+        }
+    }
+    host_state::save_state(&app_state_guard.state_path, &app_state_guard.hosts);
+    // hosts that couldn't be scanned keep their previous result for the next /diff
+    let mut last_scan = app_state_guard.last_scan.take().unwrap_or_default();
+    last_scan.retain(|address, _| hosts.contains_key(address));
+    last_scan.extend(results);
+    app_state_guard.last_scan = Some(last_scan);
+    (responses, scan_time)
+}
+
+// nmap scan of a host that is up, records its open ports and returns previously recorded ones that are closed now
+async fn refresh_open_ports(
+    app_state: &Arc<Mutex<AppState>>,
+    address: &str,
+    target: &str,
+    privileges: nmap::ScanPrivileges
+) -> Vec<String> {
+    let runner = app_state.lock().await.runner.clone();
+    let output = match nmap::scan(&runner, privileges.status_args(), target, privileges).await {
+        Ok(output) => output,
+        Err(e) => {
+            info!("Port scan of {} failed => {}", address, e);
+            return Vec::new();
+        }
+    };
+    let scan = nmap::HostScanResult::from_output(&output);
+    if !scan.up {
+        info!("Port scan of {} found it down => {}", address, output);
+        return Vec::new();
+    }
+    let mut app_state_guard = app_state.lock().await;
+    let Some(host) = app_state_guard.hosts.get_mut(address) else {
+        return Vec::new();
+    };
+    let previous = host.state.open_ports.replace(scan.open_ports.clone());
+    host_state::save_state(&app_state_guard.state_path, &app_state_guard.hosts);
+    previous
+        .map(|previous| previous.difference(&scan.open_ports).cloned().collect())
+        .unwrap_or_default()
+}
+
+// hosts whose state or open ports changed since the previous scan
+fn scan_diff(
+    previous: &HashMap<String, nmap::HostScanResult>,
+    responses: &[(String, Result<String, String>)]
+) -> String {
+    let mut lines = Vec::new();
+    for (address, output) in responses {
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                lines.push(format!("{}: scan failed, {}", address, e));
+                continue;
+            }
+        };
+        let current = nmap::HostScanResult::from_output(output);
+        let Some(before) = previous.get(address) else {
+            lines.push(format!("{}: new host, {}", address, if current.up { "up" } else { "DOWN" }));
+            continue;
+        };
+        if before.up != current.up {
+            lines.push(format!("{}: {}", address, if current.up { "came up" } else { "went DOWN" }));
+        }
+        // a host that is down has no open ports, its ports didn't close
+        if !before.up || !current.up {
+            continue;
+        }
+        let opened = current.open_ports.difference(&before.open_ports).cloned().collect::<Vec<_>>();
+        let closed = before.open_ports.difference(&current.open_ports).cloned().collect::<Vec<_>>();
+        if !opened.is_empty() {
+            lines.push(format!("{}: opened {}", address, opened.join(", ")));
+        }
+        if !closed.is_empty() {
+            lines.push(format!("{}: closed {}", address, closed.join(", ")));
+        }
+    }
+    if lines.is_empty() {
+        return "No changes since the last scan.".to_string();
+    }
+    format!("Changes since the last scan:\n{}", lines.join("\n"))
+}
+
+// seconds left before chat may run command again, otherwise records this run and returns None
+async fn scan_cooldown(
+    bot_state: &Arc<Mutex<BotState>>,
+    app_state: &Arc<Mutex<AppState>>,
+    chat_id: ChatId,
+    command: &'static str
+) -> Option<u64> {
+    let cooldown = Duration::from_secs(bot_state.lock().await.config.scan_cooldown);
+    let mut app_state_guard = app_state.lock().await;
+    let now = Instant::now();
+    if let Some(last) = app_state_guard.last_scans.get(&(chat_id, command)) {
+        let elapsed = now.duration_since(*last);
+        if elapsed < cooldown {
+            return Some((cooldown - elapsed).as_secs_f64().ceil() as u64);
+        }
+    }
+    app_state_guard.last_scans.insert((chat_id, command), now);
+    None
+}
+
+// role granted by an unexpired invite token, the token is used up
+async fn redeem_invite(app_state: &Arc<Mutex<AppState>>, token: &str) -> Option<Role> {
+    let mut app_state_guard = app_state.lock().await;
+    let now = Instant::now();
+    app_state_guard.invites.retain(|_, (_, expires)| *expires > now);
+    app_state_guard.invites.remove(token).map(|(role, _)| role)
+}
+
+// download an uploaded hosts file and add its hosts, returns the summary for the chat
+async fn import_hosts(
+    bot: &Bot,
+    app_state: &Arc<Mutex<AppState>>,
+    document: &Document,
+    replace: bool,
+    max_hosts: usize
+) -> String {
+    if document.file.size > MAX_IMPORT_SIZE {
+        return format!("File is too large, the limit is {} KB", MAX_IMPORT_SIZE / 1024);
+    }
+    let file_name = document.file_name.clone().unwrap_or_default();
+    let format = HostsFormat::from_path(Path::new(&file_name));
+
+    let mut content = Vec::new();
+    let downloaded = match bot.get_file(document.file.id.clone()).await {
+        Ok(file) => bot.download_file(&file.path, &mut content).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = downloaded {
+        error!("Unable to download {} => {}", file_name, e);
+        return format!("Unable to download {}: {}", file_name, e);
+    }
+
+    let (entries, mut skipped) = match hosts::parse_hosts(&String::from_utf8_lossy(&content), format) {
+        Ok(parsed) => (parsed.entries, parsed.invalid),
+        Err(e) => {
+            return format!("Unable to read {}: {}", file_name, e);
+        }
+    };
+
+    let mut app_state_guard = app_state.lock().await;
+    // restored if the hosts file can't be written, so the bot keeps monitoring what the file lists
+    let before = app_state_guard.hosts.clone();
+    let mut previous = std::mem::take(&mut app_state_guard.hosts);
+    let mut hosts = if replace { HashMap::new() } else { std::mem::take(&mut previous) };
+    let mut added = 0;
+    for entry in entries {
+        if hosts.contains_key(&entry.address) {
+            skipped.push((entry.address, "already listed".to_string()));
+            continue;
+        }
+        if hosts.len() >= max_hosts {
+            skipped.push((entry.address, "host limit reached".to_string()));
+            continue;
+        }
+        // hosts that stay listed after a replace keep their monitoring state
+        let state = previous
+            .remove(&entry.address)
+            .map(|host| host.state)
+            .unwrap_or_default();
+        hosts.insert(entry.address.clone(), Host { entry, state });
+        added += 1;
+    }
+    app_state_guard.hosts = hosts;
+    if let Err(e) = hosts::save_hosts(&app_state_guard.hosts_path, &app_state_guard.hosts) {
+        error!("Unable to write {} => {}", app_state_guard.hosts_path.display(), e);
+        app_state_guard.hosts = before;
+        return format!("Nothing imported, unable to write {}: {}", app_state_guard.hosts_path.display(), e);
+    }
+    host_state::save_state(&app_state_guard.state_path, &app_state_guard.hosts);
+    info!("Imported {} hosts from {}, skipped {}", added, file_name, skipped.len());
+
+    let mut summary = format!("Imported {} hosts from {}", added, file_name);
+    if replace {
+        summary.push_str(&format!(", {} hosts now monitored", app_state_guard.hosts.len()));
+    }
+    if !skipped.is_empty() {
+        summary.push_str(&format!("\nSkipped {}:", skipped.len()));
+        for (line, reason) in skipped {
+            summary.push_str(&format!("\n {} => {}", line, reason));
+        }
+    }
+    summary
+}
+
+// why add_host didn't add a host, shown to the user as is
+enum AddHostError {
+    // already listed or over max_hosts
+    Refused(String),
+    // the hosts file couldn't be written, the host was taken out again
+    Write(String),
+}
+
+impl std::fmt::Display for AddHostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddHostError::Refused(reason) | AddHostError::Write(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+// check a new host once and add it to app_state.hosts and the hosts file
+async fn add_host(
+    bot_state: &Arc<Mutex<BotState>>,
+    app_state: &Arc<Mutex<AppState>>,
+    entry: HostEntry
+) -> Result<CheckOutcome, AddHostError> {
+    let bot_config = bot_state.lock().await.config.clone();
+    {
+        let app_state_guard = app_state.lock().await;
+        if app_state_guard.hosts.contains_key(&entry.address) {
+            return Err(AddHostError::Refused(format!("Host '{}' is already listed.", entry.address)));
+        }
+        if app_state_guard.hosts.len() >= bot_config.max_hosts {
+            return Err(AddHostError::Refused(format!("Host limit reached, max_hosts is {}.", bot_config.max_hosts)));
+        }
+    }
+
+    // one check right away catches typos and sets the real initial state instead of assuming online
+    let http_clients = checks::HttpClients::new(&bot_config.tls_profiles);
+    let runner = app_state.lock().await.runner.clone();
+    let result = checks::check_host(&runner, &entry, &bot_config, &http_clients).await;
+    let mut state = HostState::default();
+    match result.status {
+        CheckStatus::Up => {
+            state.mark_online();
+            if let Some(latency) = result.latency {
+                state.record_latency(latency, bot_config.latency_threshold_ms);
+            }
+        }
+        CheckStatus::Down | CheckStatus::Unhealthy => {
+            state.mark_offline();
+        }
+        CheckStatus::Error => error!("Unable to check {} => {}", entry.address, result.output),
+    }
+
+    // the hosts map is the source of truth, the file is rewritten from it
+    let mut app_state_guard = app_state.lock().await;
+    // checked again, another /add, /import or api request may have changed the hosts during the check
+    if app_state_guard.hosts.contains_key(&entry.address) {
+        return Err(AddHostError::Refused(format!("Host '{}' is already listed.", entry.address)));
+    }
+    if app_state_guard.hosts.len() >= bot_config.max_hosts {
+        return Err(AddHostError::Refused(format!("Host limit reached, max_hosts is {}.", bot_config.max_hosts)));
+    }
+    let address = entry.address.clone();
+    app_state_guard.hosts.insert(address.clone(), Host { entry, state });
+    if let Err(e) = hosts::save_hosts(&app_state_guard.hosts_path, &app_state_guard.hosts) {
+        error!("Unable to write {} => {}", app_state_guard.hosts_path.display(), e);
+        app_state_guard.hosts.remove(&address);
+        return Err(
+            AddHostError::Write(
+                format!("Host '{}' not added, unable to write {}: {}", address, app_state_guard.hosts_path.display(), e)
+            )
+        );
+    }
+    host_state::save_state(&app_state_guard.state_path, &app_state_guard.hosts);
+    info!("Added {} to hosts", address);
+
+    Ok(result)
+}
+
+// /config arguments split on whitespace, only the subcommand and the field name of edit are
+// case-insensitive and lowercased, values are kept as typed
+fn config_args(args: &str) -> Vec<String> {
+    let mut args = args.split_whitespace().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    if let Some(subcommand) = args.first_mut() {
+        subcommand.make_ascii_lowercase();
+    }
+    if args.first().is_some_and(|subcommand| subcommand == "edit") && let Some(field) = args.get_mut(1) {
+        field.make_ascii_lowercase();
+    }
+    args
+}
+
+// remove host from app_state.hosts and the hosts file, returns false if the host was not found
+// and an error if the hosts file could not be written, the host is kept then
+async fn remove_host(app_state: &Arc<Mutex<AppState>>, host: &str) -> Result<bool, String> {
+    let mut app_state_guard = app_state.lock().await;
+
+    // remove hosts from app_state.hosts
+    let Some(removed) = app_state_guard.hosts.remove(host) else {
+        return Ok(false);
+    };
+
+    // write new hosts file
+    if let Err(e) = hosts::save_hosts(&app_state_guard.hosts_path, &app_state_guard.hosts) {
+        error!("Unable to write {} => {}", app_state_guard.hosts_path.display(), e);
+        let reason = format!("Unable to write {}: {}", app_state_guard.hosts_path.display(), e);
+        app_state_guard.hosts.insert(host.to_string(), removed);
+        return Err(reason);
+    }
+    host_state::save_state(&app_state_guard.state_path, &app_state_guard.hosts);
+    info!("Removed {} from hosts", host);
+
+    Ok(true)
+}
+
+async fn set_ping_interval(bot_state: &Arc<Mutex<BotState>>, value: &str) -> String {
+    match value.parse::<u64>() {
+        Ok(value) if !(MIN_PING_INTERVAL..=MAX_PING_INTERVAL).contains(&value) => {
+            format!("Ping interval must be from {} to {} seconds", MIN_PING_INTERVAL, MAX_PING_INTERVAL)
+        }
+        Ok(value) => {
+            let mut bot_state_guard = bot_state.lock().await;
+            let old = bot_state_guard.config.ping_interval;
+            bot_state_guard.config.ping_interval = value;
+            // wakes the running loop, so the new interval applies from now instead of after the current wait
+            bot_state_guard.interval_changed.notify_waiters();
+            // write new config to file
+            let saved = save_config(&bot_state_guard.config).await;
+            info!("Ping interval changed from {} to {}", old, value);
+            saved_reply(format!("Ping interval changed from {}s to {}s", old, value), saved)
+        }
+        Err(e) => format!("Invalid value: {}", e),
+    }
+}
+
+async fn set_alert_chat(bot_state: &Arc<Mutex<BotState>>, alert_chat_id: Option<i64>) -> Result<(), String> {
+    let mut bot_state_guard = bot_state.lock().await;
+    bot_state_guard.config.alert_chat_id = alert_chat_id;
+    info!("Alert chat set to {:?}", alert_chat_id);
+    save_config(&bot_state_guard.config).await
+}
+
+// commands the monitoring loop and /status would run for a host, as the program and its argument vector
+fn debug_message(entry: &HostEntry, bot_config: &BotConfig, interval: u64) -> String {
+    let argv = |(program, args): (&str, Vec<String>)| {
+        format!("{:?}", std::iter::once(program.to_string()).chain(args).collect::<Vec<_>>())
+    };
+    let check = match &entry.method {
+        CheckMethod::Ping => {
+            let check = checks::check_command(entry, bot_config).map(argv).unwrap_or_default();
+            match entry.fallback.unwrap_or(bot_config.fallback) {
+                checks::Fallback::None => check,
+                fallback => format!("{}, fallback {} when it fails", check, fallback),
+            }
+        }
+        CheckMethod::Tcp { host, port, .. } => format!("tcp connect to {}:{}, no command is run", host, port),
+        CheckMethod::Http { url, .. } => format!("http get {}, no command is run", url),
+        CheckMethod::Subnet { network } => {
+            format!("each of the {} addresses of {} is checked like a plain host", network.hosts().count(), network)
+        }
+    };
+    let privileges = bot_config.scan_privileges;
+    let scan = argv(nmap::command_line(privileges.status_args(), &entry.scan_target(), privileges));
+    format!(
+        "{}\nmonitoring check: {}\n/status scan: {}\ninterval: {}s",
+        entry.display_name(),
+        check,
+        scan,
+        interval
+    )
+}
+
+async fn set_quiet_hours(
+    bot_state: &Arc<Mutex<BotState>>,
+    quiet_hours: Option<maintenance::Schedule>
+) -> Result<(), String> {
+    let mut bot_state_guard = bot_state.lock().await;
+    info!("Quiet hours set to {:?}", quiet_hours.as_ref().map(ToString::to_string));
+    bot_state_guard.config.quiet_hours = quiet_hours;
+    save_config(&bot_state_guard.config).await
+}
+
+async fn config_list_message(bot_state: &Arc<Mutex<BotState>>) -> String {
+    let mut bot_config = {
+        let bot_state_guard = bot_state.lock().await;
+        bot_state_guard.config.clone()
+    };
+    // don't leak secrets into the chat
+    if bot_config.api_token.is_some() {
+        bot_config.api_token = Some("<redacted>".to_string());
+    }
+    if bot_config.discord_webhook_url.is_some() {
+        bot_config.discord_webhook_url = Some("<redacted>".to_string());
+    }
+    if let Some(email) = &mut bot_config.email && email.password.is_some() {
+        email.password = Some("<redacted>".to_string());
+    }
+    for token in &mut bot_config.backup_bot_tokens {
+        *token = "<redacted>".to_string();
+    }
+    // webhook urls often hold a token, eg. slack
+    for webhook in &mut bot_config.webhooks {
+        webhook.url = "<redacted>".to_string();
+    }
+    format!("{:?}", bot_config)
+}
+
+// config file content with secrets replaced, comments and formatting are kept
+fn redact_config(content: &str) -> String {
+    let Ok(mut document) = content.parse::<DocumentMut>() else {
+        // don't risk sending secrets from a file that can't be parsed
+        return "# config.toml could not be parsed for redaction\n".to_string();
+    };
+    for key in ["api_token", "discord_webhook_url"] {
+        if document.contains_key(key) {
+            document[key] = toml_edit::value("<redacted>");
+        }
+    }
+    if let Some(email) = document.get_mut("email").and_then(|email| email.as_table_like_mut()) &&
+        email.contains_key("password")
+    {
+        email.insert("password", toml_edit::value("<redacted>"));
+    }
+    if let Some(tokens) = document.get_mut("backup_bot_tokens").and_then(|tokens| tokens.as_array_mut()) {
+        for token in tokens.iter_mut() {
+            *token = "<redacted>".into();
+        }
+    }
+    if let Some(webhooks) = document.get_mut("webhooks").and_then(|webhooks| webhooks.as_array_of_tables_mut()) {
+        for webhook in webhooks.iter_mut().filter(|webhook| webhook.contains_key("url")) {
+            webhook.insert("url", toml_edit::value("<redacted>"));
+        }
+    }
+    document.to_string()
+}
+
+// write config to config.toml, a failure is logged and returned as a reply for the chat
+async fn save_config(config: &BotConfig) -> Result<(), String> {
+    let result = write_bot_config(config).await;
+    if let Err(e) = &result {
+        error!("Unable to write {} => {}", CONFIG_PATH, e);
+    }
+    result.map_err(|e| format!("The change applies until a restart, unable to write {}: {}", CONFIG_PATH, e))
+}
+
+// reply to a config change, with the reason if it could not be saved
+fn saved_reply(reply: String, saved: Result<(), String>) -> String {
+    match saved {
+        Ok(()) => reply,
+        Err(e) => format!("{}\n{}", reply, e),
+    }
+}
+
+async fn write_bot_config(config: &BotConfig) -> Result<(), String> {
+    let toml_config = toml::to_string(config).map_err(|e| e.to_string())?;
+    // edit the existing file in place so comments and unknown keys survive
+    let existing = fs::read_to_string(CONFIG_PATH).await
+        .ok()
+        .and_then(|content| content.parse::<DocumentMut>().ok());
+    let content = match existing {
+        Some(mut document) => {
+            let new_document = toml_config.parse::<DocumentMut>().map_err(|e| e.to_string())?;
+            merge_toml_table(document.as_table_mut(), new_document.as_table());
+            // options set back to None are missing from the new config, drop them but keep unknown keys
+            let removed = document
+                .iter()
+                .filter(|(key, item)| !new_document.contains_key(key) && is_config_field(key, item))
+                .map(|(key, _)| key.to_string())
+                .collect::<Vec<_>>();
+            for key in removed {
+                document.remove(&key);
+            }
+            document.to_string()
+        }
+        None => toml_config,
+    };
+    write_config(&content).await.map_err(|e| e.to_string())
+}
+
+// replace config.toml through a temporary file so a crash never leaves it half written
+async fn write_config(content: &str) -> std::io::Result<()> {
+    let temp_path = format!("{}.tmp", CONFIG_PATH);
+    let result = match fs::write(&temp_path, content).await {
+        Ok(()) => fs::rename(&temp_path, CONFIG_PATH).await,
+        Err(e) => Err(e),
+    };
+    health::set_config_ok(result.is_ok());
+    result
+}
+
+// restore BotConfig::default(), the file is written from scratch so no old keys or comments remain
+async fn reset_config(bot_state: &Arc<Mutex<BotState>>) -> String {
+    let config = BotConfig::default();
+    let content = match toml::to_string(&config) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Unable to serialize default config => {}", e);
+            return "Config not reset, the default config could not be serialized.".to_string();
+        }
+    };
+    let mut bot_state_guard = bot_state.lock().await;
+    if let Err(e) = write_config(&content).await {
+        error!("Unable to write {} => {}", CONFIG_PATH, e);
+        return format!("Config not reset, unable to write {}: {}", CONFIG_PATH, e);
+    }
+    bot_state_guard.config = config;
+    info!("Config reset to defaults");
+    "Config reset to defaults. Restart monitoring with /stop and /start to apply it to a running check loop.".to_string()
+}
+
+// copy values from source into target, keeping the comments and formatting of values that didn't change
+fn merge_toml_table(target: &mut toml_edit::Table, source: &toml_edit::Table) {
+    for (key, item) in source.iter() {
+        match (target.get_mut(key), item) {
+            (Some(toml_edit::Item::Table(target)), toml_edit::Item::Table(source)) => {
+                merge_toml_table(target, source);
+            }
+            (Some(toml_edit::Item::Value(existing)), toml_edit::Item::Value(value)) => {
+                let mut bare = existing.clone();
+                bare.decor_mut().clear();
+                if bare.to_string() != value.to_string() {
+                    let decor = existing.decor().clone();
+                    *existing = value.clone();
+                    *existing.decor_mut() = decor;
+                }
+            }
+            _ => {
+                target.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct ConfigIssues {
+    // values the bot can't run with
+    errors: Vec<String>,
+    // likely mistakes, the bot still runs
+    warnings: Vec<String>,
+}
+
+impl ConfigIssues {
+    fn report(&self) -> String {
+        if self.errors.is_empty() && self.warnings.is_empty() {
+            return "config.toml is valid".to_string();
+        }
+        let mut lines = Vec::new();
+        lines.extend(self.errors.iter().map(|e| format!("Error: {}", e)));
+        lines.extend(self.warnings.iter().map(|warning| format!("Warning: {}", warning)));
+        lines.join("\n")
+    }
+}
+
+// parse config file content and check values serde accepts but the bot can't use, and keys that fell back to defaults
+fn validate_config(content: &str) -> Result<(BotConfig, ConfigIssues), String> {
+    let config = toml::from_str::<BotConfig>(content).map_err(|e| e.to_string())?;
+    let document = content.parse::<DocumentMut>().map_err(|e| e.to_string())?;
+    let mut issues = ConfigIssues::default();
+
+    if !(MIN_PING_INTERVAL..=MAX_PING_INTERVAL).contains(&config.ping_interval) {
+        issues.errors.push(
+            format!(
+                "ping_interval {} must be from {} to {} seconds",
+                config.ping_interval,
+                MIN_PING_INTERVAL,
+                MAX_PING_INTERVAL
+            )
+        );
+    }
+    if config.latency_threshold_ms < 0.0 {
+        issues.errors.push(format!("latency_threshold_ms {} must not be negative", config.latency_threshold_ms));
+    }
+    if !(0.0..=100.0).contains(&config.loss_threshold) {
+        issues.errors.push(format!("loss_threshold {} must be a percentage from 0 to 100", config.loss_threshold));
+    }
+    let retention = &config.retention;
+    if retention.latency_samples == 0 || retention.scan_history == 0 || retention.state_changes == 0 {
+        issues.errors.push("[retention] latency_samples, scan_history and state_changes must be at least 1".to_string());
+    }
+    if retention.outage_days < 1 {
+        issues.errors.push(format!("[retention] outage_days {} must be at least 1", retention.outage_days));
+    }
+    issues.errors.extend(checks::validate_tls_profiles(&config.tls_profiles));
+    let resources = &config.resources;
+    for (key, percent) in [
+        ("cpu_percent", resources.cpu_percent),
+        ("memory_percent", resources.memory_percent),
+        ("disk_percent", resources.disk_percent),
+    ] {
+        if !(0.0..=100.0).contains(&percent) {
+            issues.errors.push(format!("[resources] {} {} must be a percentage from 0 to 100", key, percent));
+        }
+    }
+    if config.flap_threshold > 0 && config.flap_window == 0 {
+        issues.errors.push("flap_window must be at least 1 second when flap_threshold is set".to_string());
+    }
+    if let Some(email) = &config.email && email.to.is_empty() {
+        issues.errors.push("[email] has no recipients in to".to_string());
+    }
+    if config.api_token.is_some() && config.http_bind.is_none() {
+        issues.warnings.push("api_token is set but the http api is disabled without http_bind".to_string());
+    }
+    if config.http_bind.is_some() && config.api_token.is_none() {
+        issues.warnings.push("http_bind is set without api_token, every api request is refused".to_string());
+    }
+    if config.health_bind.is_some() && config.health_bind == config.http_bind {
+        issues.errors.push("health_bind must differ from http_bind, both servers can't listen on one address".to_string());
+    }
+    if let Some(url) = &config.discord_webhook_url && !url.starts_with("https://") {
+        issues.warnings.push("discord_webhook_url should be an https:// url".to_string());
+    }
+    for webhook in &config.webhooks {
+        if let Err(e) = reqwest::Url::parse(&webhook.url) {
+            // the url is left out, it may hold a token
+            issues.errors.push(format!("[[webhooks]] invalid url: {}", e));
+        }
+        if reqwest::header::HeaderValue::from_str(&webhook.content_type).is_err() {
+            issues.errors.push(format!("[[webhooks]] invalid content_type '{}'", webhook.content_type));
+        }
+    }
+
+    let defaults = toml::to_string(&BotConfig::default())
+        .ok()
+        .and_then(|defaults| defaults.parse::<DocumentMut>().ok())
+        .unwrap_or_default();
+    // unknown keys are ignored by serde, usually a typo of a real option
+    for (key, item) in document.iter() {
+        if !defaults.contains_key(key) && !is_config_field(key, item) {
+            issues.warnings.push(format!("unknown key '{}' is ignored", key));
+        }
+    }
+    // options without a default of "disabled" that are missing from the file
+    let missing = defaults
+        .iter()
+        .filter(|(key, item)| item.is_value() && !document.contains_key(key))
+        .filter(|(_, item)| !item.as_array().is_some_and(|array| array.is_empty()))
+        .map(|(key, item)| format!("{} = {}", key, item.to_string().trim()))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        issues.warnings.push(format!("not set, using defaults: {}", missing.join(", ")));
+    }
+
+    Ok((config, issues))
+}
+
+// true if key is a BotConfig field, unknown keys are ignored when deserializing so they leave the default unchanged
+fn is_config_field(key: &str, item: &toml_edit::Item) -> bool {
+    let mut document = DocumentMut::new();
+    document.insert(key, item.clone());
+    let Ok(config) = toml::from_str::<BotConfig>(&document.to_string()) else {
+        return true;
+    };
+    toml::to_string(&config).ok() != toml::to_string(&BotConfig::default()).ok()
+}
+
+// numbered host list with the state of each host, sorted by address
+fn host_list<'a>(hosts: impl Iterator<Item = &'a Host>) -> String {
+    let mut hosts = hosts.collect::<Vec<_>>();
+    hosts.sort_by(|a, b| a.entry.address.cmp(&b.entry.address));
+    hosts
+        .iter()
+        .enumerate()
+        .map(|(index, host)| {
+            let status = match (host.state.online, host.state.pending) {
+                (_, true) => "pending",
+                (true, false) => "online",
+                (false, false) => "OFFLINE",
+            };
+            let critical = if host.entry.critical { " [CRITICAL]" } else { "" };
+            let members = match &host.entry.method {
+                CheckMethod::Subnet { network } => {
+                    format!(" ({}/{} up)", host.state.subnet_up.len(), network.hosts().count())
+                }
+                _ => String::new(),
+            };
+            format!(" {}: {} - {}{}{}", index + 1, host.entry.display_name(), status, members, critical)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// append the host's note to an alert
+fn with_note(text: String, entry: &HostEntry) -> String {
+    match &entry.note {
+        Some(note) => format!("{}\nNote: {}", text, note),
+        None => text,
+    }
+}
+
+// /status flags, applied to the scan results before they are formatted
+#[derive(Default)]
+struct StatusFilter {
+    // only hosts that are up (true) or down (false)
+    up: Option<bool>,
+    by_latency: bool,
+}
+
+impl StatusFilter {
+    fn parse(args: &str) -> Result<StatusFilter, String> {
+        let mut filter = StatusFilter::default();
+        let mut args = args.split_whitespace();
+        while let Some(arg) = args.next() {
+            match arg {
+                "--down" => filter.up = Some(false),
+                "--up" => filter.up = Some(true),
+                "--sort" =>
+                    match args.next() {
+                        Some("latency") => filter.by_latency = true,
+                        Some("name") => filter.by_latency = false,
+                        Some(key) => return Err(format!("Unknown sort key '{}'", key)),
+                        None => return Err("Missing sort key".to_string()),
+                    }
+                arg => return Err(format!("Unknown option '{}'", arg)),
+            }
+        }
+        Ok(filter)
+    }
+
+    // results come sorted by name, sorting by latency puts hosts without one last
+    fn apply(&self, mut results: Vec<(String, CheckOutcome)>) -> Vec<(String, CheckOutcome)> {
+        if let Some(up) = self.up {
+            results.retain(|(_, outcome)| (outcome.status == CheckStatus::Up) == up);
+        }
+        if self.by_latency {
+            results.sort_by(|(_, a), (_, b)| {
+                match (a.latency, b.latency) {
+                    (Some(a), Some(b)) => a.total_cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            });
+        }
+        results
+    }
+}
+
+// names of hosts without a check since the bot or the monitoring task started, sorted
+fn pending_hosts(hosts: &HashMap<String, Host>) -> Vec<String> {
+    let mut pending = hosts
+        .values()
+        .filter(|host| host.state.pending)
+        .map(|host| host.entry.display_name())
+        .collect::<Vec<_>>();
+    pending.sort();
+    pending
+}
+
+// hosts muted with /snooze and when their snooze ends
+fn snoozed_hosts(hosts: &HashMap<String, Host>) -> Vec<String> {
+    let mut snoozed = hosts
+        .values()
+        .filter(|host| host.state.snoozed())
+        .filter_map(|host| {
+            let until = host.state.snoozed_until?;
+            Some(format!("{} until {}", host.entry.display_name(), messages::format_timestamp(until)))
+        })
+        .collect::<Vec<_>>();
+    snoozed.sort();
+    snoozed
+}
+
+// list of hosts the monitoring loop has marked offline, acknowledged outages are marked
+fn outages_summary(hosts: &HashMap<String, Host>) -> String {
+    let mut outages = hosts
+        .iter()
+        .map(|(address, host)| (address, &host.state))
+        .filter(|(_, state)| !state.online)
+        .map(|(host, state)| {
+            let duration = state.offline_since
+                .map(|since| format!(" for {}", host_state::format_duration(chrono::Utc::now() - since)))
+                .unwrap_or_default();
+            let ack = if state.acknowledged { " [ACK]" } else { "" };
+            format!(" {} offline{}{}", host, duration, ack)
+        })
+        .collect::<Vec<_>>();
+    if outages.is_empty() {
+        return String::new();
+    }
+    outages.sort();
+    format!("Outages:\n{}\n\n", outages.join("\n"))
+}
+
+// current and recent average latency of a host for /latency
+fn latency_line(host: &str, state: &HostState) -> String {
+    match (state.current_latency(), state.average_latency()) {
+        (Some(current), Some(average)) => {
+            let degraded = if state.degraded || state.lossy { " [DEGRADED]" } else { "" };
+            let loss = state.packet_loss
+                .filter(|loss| *loss > 0.0)
+                .map(|loss| format!(", {:.0}% packet loss", loss))
+                .unwrap_or_default();
+            format!(
+                " {}: {:.1} ms, average {:.1} ms over {} checks{}{}",
+                host,
+                current,
+                average,
+                state.latency_history.len(),
+                loss,
+                degraded
+            )
+        }
+        _ => format!(" {}: no latency data yet", host),
+    }
+}
+
+// state changes of a host oldest first, recoveries with the length of the outage
+fn history_message(name: &str, state: &HostState) -> String {
+    if state.history.is_empty() {
+        return format!("No state changes recorded for {}.", name);
+    }
+    let mut offline_since = None;
+    let lines = state.history
+        .iter()
+        .map(|change| {
+            let time = messages::format_timestamp(change.time);
+            if !change.online {
+                offline_since = Some(change.time);
+                return format!(" {} down", time);
+            }
+            match offline_since.take() {
+                Some(since) => format!(" {} up after {}", time, host_state::format_duration(change.time - since)),
+                None => format!(" {} up", time),
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("History of {}:\n{}", name, lines.join("\n"))
+}
+
+// split text into chunks of at most max_length bytes, preferring line boundaries
+fn split_message(text: &str, max_length: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for line in text.split_inclusive('\n') {
+        if current.len() + line.len() > max_length && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if line.len() > max_length {
+            // a single line is too long, hard split it on char boundaries
+            for c in line.chars() {
+                if current.len() + c.len_utf8() > max_length {
+                    chunks.push(std::mem::take(&mut current));
+                }
+                current.push(c);
+            }
+        } else {
+            current.push_str(line);
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// send text to chat, split into several messages when it exceeds telegram's length limit
+async fn send_chunked(bot: &Bot, chat_id: ChatId, text: &str) -> Result<(), RequestError> {
+    for chunk in split_message(text, MAX_MESSAGE_LENGTH) {
+        bot.send_message(chat_id, chunk).await?;
+    }
+    Ok(())
+}
+
+// deliver a notification to the chats and mirror it to the other configured transports once,
+// failures are logged and the chats that failed are returned
+async fn notify(
+    bot: &Bot,
+    http_client: &reqwest::Client,
+    bot_config: &BotConfig,
+    chat_ids: &[ChatId],
+    notification: &Notification
+) -> Vec<(ChatId, RequestError)> {
+    let message = bot_config.severity_prefixes.apply(notification);
+    let mut failed = Vec::new();
+    for &chat_id in chat_ids {
+        // the chat's /prefs only affect telegram, discord and email get every notification
+        let preferences = bot_config.chat_preferences.get(&chat_id.to_string()).cloned().unwrap_or_default();
+        let Some(chat_message) = preferences.apply(notification, &message) else {
+            continue;
+        };
+        if let Err(e) = send_with_retry(bot, chat_id, &chat_message, bot_config.send_retries).await {
+            error!("Failed to send notification to Chat ID {} => {}", chat_id, e);
+            failed.push((chat_id, e));
+        }
+        // chat ids are the same for every bot, each backup bot must have been started in or added to the chat
+        for (i, token) in bot_config.backup_bot_tokens.iter().enumerate() {
+            // same client as the main bot, so TELEGRAM_PROXY applies to backup bots too
+            let backup_bot = Bot::with_client(token, bot.client().clone());
+            if let Err(e) = send_with_retry(&backup_bot, chat_id, &chat_message, bot_config.send_retries).await {
+                error!("Failed to send notification with backup bot {} => {}", i + 1, e);
+            }
+        }
+    }
+    if
+        let Some(url) = &bot_config.discord_webhook_url &&
+        let Err(e) = discord::send(http_client, url, &message).await
+    {
+        error!("Failed to send Discord notification => {}", e);
+    }
+    for webhook in &bot_config.webhooks {
+        for event in &notification.events {
+            if let Err(e) = webhook::send(http_client, webhook, webhook.payload(event, &message)).await {
+                error!("Failed to send webhook notification for {} => {}", event.host, e);
+            }
+        }
+    }
+    // sent in the background, a slow smtp server must not hold up the monitoring loop
+    if let Some(email_config) = &bot_config.email && notification.severity >= email_config.min_severity {
+        let email_config = email_config.clone();
+        let subject = notification.text.lines().next().unwrap_or_default().to_string();
+        tokio::spawn(async move {
+            if let Err(e) = email::send(&email_config, &subject, &message).await {
+                error!("Failed to send email notification => {}", e);
+            }
+        });
+    }
+    failed
+}
+
+// telegram errors that won't go away by retrying, the chat was deleted or the bot was blocked or removed
+fn is_chat_gone(error: &RequestError) -> bool {
+    matches!(
+        error,
+        RequestError::Api(
+            ApiError::BotBlocked |
+                ApiError::ChatNotFound |
+                ApiError::GroupDeactivated |
+                ApiError::BotKicked |
+                ApiError::BotKickedFromSupergroup |
+                ApiError::BotKickedFromChannel |
+                ApiError::UserDeactivated |
+                ApiError::CantInitiateConversation
+        )
+    )
+}
+
+// send_chunked that retries transient errors with exponential backoff, honoring telegram's retry after
+async fn send_with_retry(bot: &Bot, chat_id: ChatId, text: &str, retries: u32) -> Result<(), RequestError> {
+    for chunk in split_message(text, MAX_MESSAGE_LENGTH) {
+        let mut attempt = 0;
+        loop {
+            let error = match bot.send_message(chat_id, chunk.clone()).await {
+                Ok(_) => break,
+                Err(e) => e,
+            };
+            let delay = match &error {
+                RequestError::RetryAfter(seconds) => seconds.duration(),
+                // invalid json is usually an error page from a proxy in front of telegram
+                RequestError::Network(_) | RequestError::Io(_) | RequestError::InvalidJson { .. } =>
+                    SEND_RETRY_BASE_DELAY * 2u32.pow(attempt.min(6)),
+                _ => {
+                    return Err(error);
+                }
+            };
+            if attempt >= retries {
+                return Err(error);
+            }
+            attempt += 1;
+            warn!("Sending to Chat ID {} failed, retry {}/{} in {:?} => {}", chat_id, attempt, retries, delay, error);
+            sleep(delay).await;
         }
     }
+    Ok(())
+}
 
+// send MarkdownV2 formatted blocks, several blocks are combined per message without splitting a block
+async fn send_chunked_md(bot: &Bot, chat_id: ChatId, blocks: &[String]) -> Result<(), RequestError> {
+    let mut current = String::new();
+    for block in blocks {
+        if !current.is_empty() && current.len() + block.len() + 2 > MAX_MESSAGE_LENGTH {
+            bot.send_message(chat_id, std::mem::take(&mut current))
+                .parse_mode(ParseMode::MarkdownV2).await?;
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(block);
+    }
+    if !current.is_empty() {
+        bot.send_message(chat_id, current).parse_mode(ParseMode::MarkdownV2).await?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_args_lowercase_names_but_not_values() {
+        assert_eq!(
+            config_args("EDIT Ping_Args -W 1 -I Eth0"),
+            ["edit", "ping_args", "-W", "1", "-I", "Eth0"]
+        );
+        assert_eq!(config_args("Edit PING_INTERVAL 30"), ["edit", "ping_interval", "30"]);
+        assert_eq!(config_args("LIST"), ["list"]);
+        // only the field of edit is a name
+        assert_eq!(config_args("Validate Extra"), ["validate", "Extra"]);
+        assert!(config_args("  ").is_empty());
+    }
+
+    #[test]
+    fn scan_diff_reports_failed_scan_instead_of_host_down() {
+        let previous = HashMap::from([
+            ("10.0.0.5".to_string(), nmap::HostScanResult { up: true, open_ports: Default::default() }),
+        ]);
+        let responses = [("10.0.0.5".to_string(), Err("unable to run nmap => not found".to_string()))];
+        assert_eq!(
+            scan_diff(&previous, &responses),
+            "Changes since the last scan:\n10.0.0.5: scan failed, unable to run nmap => not found"
+        );
+    }
+
+    // holds the ping of one host until released, every command succeeds
+    struct GatedRunner {
+        host: &'static str,
+        entered: Arc<tokio::sync::Notify>,
+        release: Arc<tokio::sync::Notify>,
+    }
+
+    impl runner::CommandRunner for GatedRunner {
+        fn run<'a>(&'a self, program: &'a str, args: &'a [String]) -> runner::RunFuture<'a> {
+            Box::pin(async move {
+                if program == "ping" && args.last().is_some_and(|host| host == self.host) {
+                    self.entered.notify_one();
+                    self.release.notified().await;
+                }
+                Ok(runner::CommandOutput { success: true, ..Default::default() })
+            })
+        }
+    }
+
+    // runs one monitoring cycle over hosts and calls change while the check of the first host is in flight
+    async fn change_during_cycle(hosts: &[&'static str], change: impl FnOnce(&mut AppState)) -> Arc<Mutex<AppState>> {
+        let entered = Arc::new(tokio::sync::Notify::new());
+        let release = Arc::new(tokio::sync::Notify::new());
+        let runner = GatedRunner { host: hosts[0], entered: Arc::clone(&entered), release: Arc::clone(&release) };
+        let state_path = std::env::temp_dir().join(
+            format!("notification_bot_state_{}_{}.json", hosts.len(), std::process::id())
+        );
+        let app_state = Arc::new(
+            Mutex::new(AppState {
+                hosts: hosts
+                    .iter()
+                    .map(|address| {
+                        let host = Host { entry: HostEntry::parse(address).unwrap(), state: HostState::default() };
+                        (address.to_string(), host)
+                    })
+                    .collect(),
+                state_path: state_path.clone(),
+                hosts_path: state_path.with_extension("txt"),
+                runner: runner::Runner::new(runner),
+                ..Default::default()
+            })
+        );
+        // last_chat_id matches so config.toml isn't written, the grace keeps notifications from being sent
+        let config = BotConfig { last_chat_id: Some(1), startup_grace: 3600, ..BotConfig::default() };
+        let bot_state = Arc::new(Mutex::new(BotState { config, ..Default::default() }));
+        let bot = Bot::new("123:test");
+
+        assert!(start_monitoring(&bot, &bot_state, &app_state, ChatId(1)).await);
+        let started = bot_state.lock().await.last_cycle;
+        bot_state.lock().await.check_now.notify_one();
+        tokio::time::timeout(Duration::from_secs(5), entered.notified()).await.unwrap();
+        change(&mut *app_state.lock().await);
+        release.notify_one();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while bot_state.lock().await.last_cycle == started {
+                sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+        stop_monitoring(&bot_state).await.unwrap();
+        let _ = std::fs::remove_file(&state_path);
+        app_state
+    }
+
+    #[tokio::test]
+    async fn host_added_during_cycle_is_kept() {
+        let app_state = change_during_cycle(&["10.0.0.5"], |app_state| {
+            let entry = HostEntry::parse("10.0.0.6").unwrap();
+            app_state.hosts.insert("10.0.0.6".to_string(), Host { entry, state: HostState::default() });
+        }).await;
+        let app_state_guard = app_state.lock().await;
+        assert!(app_state_guard.hosts.contains_key("10.0.0.5"));
+        assert!(app_state_guard.hosts.contains_key("10.0.0.6"));
+    }
+
+    #[tokio::test]
+    async fn host_removed_during_its_check_stays_removed() {
+        let app_state = change_during_cycle(&["10.0.0.5", "10.0.0.6"], |app_state| {
+            app_state.hosts.remove("10.0.0.5");
+        }).await;
+        let app_state_guard = app_state.lock().await;
+        assert!(!app_state_guard.hosts.contains_key("10.0.0.5"));
+        assert!(app_state_guard.hosts.contains_key("10.0.0.6"));
+    }
+
+    const PING_DOWN: &str = "3 packets transmitted, 0 received, 100% packet loss";
+
+    fn mock_ping(path: &Path, success: bool) {
+        let stdout = if success { "3 packets transmitted, 3 received, 0% packet loss" } else { PING_DOWN };
+        let response = format!("[[response]]\nprogram = \"ping\"\nsuccess = {}\nstdout = \"{}\"\n", success, stdout);
+        std::fs::write(path, response).unwrap();
+    }
+
+    // next text the bot sent through the fake telegram api
+    async fn next_message(messages: &mut mpsc::UnboundedReceiver<String>) -> String {
+        let body = tokio::time::timeout(Duration::from_secs(10), messages.recv()).await.unwrap().unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        body["text"].as_str().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn offline_and_recovery_through_mock_runner() {
+        // fake telegram api that hands every sent message to the test
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let (messages_tx, mut messages) = mpsc::unbounded_channel();
+        tokio::spawn(
+            http::serve(format!("127.0.0.1:{}", port), move |request: http::Request| {
+                let messages_tx = messages_tx.clone();
+                async move {
+                    let _ = messages_tx.send(request.body);
+                    let message = serde_json::json!({
+                        "message_id": 1,
+                        "date": 0,
+                        "chat": { "id": 1, "type": "private" },
+                        "text": "ok",
+                    });
+                    http::Response::json(200, serde_json::json!({ "ok": true, "result": message }))
+                }
+            })
+        );
+        sleep(Duration::from_millis(50)).await;
+        let bot = Bot::new("123:test").set_api_url(reqwest::Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap());
+
+        let mock_path = std::env::temp_dir().join(format!("notification_bot_mock_{}.toml", std::process::id()));
+        let state_path = std::env::temp_dir().join(format!("notification_bot_mock_state_{}.json", std::process::id()));
+        mock_ping(&mock_path, false);
+        let host = Host { entry: HostEntry::parse("10.0.0.5").unwrap(), state: HostState::default() };
+        let app_state = Arc::new(
+            Mutex::new(AppState {
+                hosts: HashMap::from([("10.0.0.5".to_string(), host)]),
+                state_path: state_path.clone(),
+                hosts_path: state_path.with_extension("txt"),
+                runner: runner::Runner::new(runner::MockRunner::new(&mock_path)),
+                ..Default::default()
+            })
+        );
+        let config = BotConfig { last_chat_id: Some(1), ..BotConfig::default() };
+        let bot_state = Arc::new(Mutex::new(BotState { config, ..Default::default() }));
+
+        assert!(start_monitoring(&bot, &bot_state, &app_state, ChatId(1)).await);
+        bot_state.lock().await.check_now.notify_one();
+        let text = next_message(&mut messages).await;
+        assert!(text.contains("HOST OFFLINE") && text.contains(PING_DOWN), "{}", text);
+        {
+            let app_state_guard = app_state.lock().await;
+            let state = &app_state_guard.hosts["10.0.0.5"].state;
+            assert!(!state.online);
+            assert!(state.offline_since.is_some());
+        }
+
+        mock_ping(&mock_path, true);
+        bot_state.lock().await.check_now.notify_one();
+        let text = next_message(&mut messages).await;
+        assert!(text.contains("HOST ONLINE -> 10.0.0.5 was offline"), "{}", text);
+        assert!(app_state.lock().await.hosts["10.0.0.5"].state.online);
+
+        stop_monitoring(&bot_state).await.unwrap();
+        let _ = std::fs::remove_file(&mock_path);
+        let _ = std::fs::remove_file(&state_path);
+    }
+}
@@ -0,0 +1,101 @@
+use chrono::{ DateTime, Datelike, Local, NaiveTime, Timelike, Weekday };
+use serde::{ Serialize, Deserialize };
+
+// daily time range, optionally limited to some days of the week
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Schedule {
+    // local time, "HH:MM" or "HH:MM:SS", a schedule may wrap past midnight eg. 23:00 - 01:00
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    // days the range starts on, empty means every day
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+}
+
+impl Schedule {
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        let time = now.time();
+        if self.start <= self.end {
+            self.starts_on(now.weekday()) && time >= self.start && time < self.end
+        } else if time >= self.start {
+            // before midnight, range started today
+            self.starts_on(now.weekday())
+        } else if time < self.end {
+            // after midnight, range started yesterday
+            self.starts_on(now.weekday().pred())
+        } else {
+            false
+        }
+    }
+
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    // "22:00-07:00" or with days "22:00-07:00@Fri,Sat"
+    pub fn parse(text: &str) -> Result<Schedule, String> {
+        let (range, days) = match text.split_once('@') {
+            Some((range, days)) => (range, Some(days)),
+            None => (text, None),
+        };
+        let (start, end) = range.split_once('-').ok_or(format!("expected HH:MM-HH:MM, got '{}'", range))?;
+        let days = days
+            .map(|days| {
+                days.split(',')
+                    .map(|day| day.parse::<Weekday>().map_err(|_| format!("invalid day '{}'", day)))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        Ok(Schedule { start: parse_time(start)?, end: parse_time(end)?, days })
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", format_time(self.start), format_time(self.end))?;
+        if !self.days.is_empty() {
+            let days = self.days
+                .iter()
+                .map(|day| day.to_string())
+                .collect::<Vec<_>>();
+            write!(f, "@{}", days.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_time(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M:%S"))
+        .map_err(|_| format!("invalid time '{}'", text))
+}
+
+fn format_time(time: NaiveTime) -> String {
+    if time.second() == 0 {
+        time.format("%H:%M").to_string()
+    } else {
+        time.format("%H:%M:%S").to_string()
+    }
+}
+
+// scheduled window during which notifications for the matching hosts are muted
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MaintenanceWindow {
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    // hosts the window applies to, empty means all hosts
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+impl MaintenanceWindow {
+    fn applies_to(&self, host: &str) -> bool {
+        self.hosts.is_empty() || self.hosts.iter().any(|h| h == host)
+    }
+}
+
+// true if host is inside any active maintenance window at the given time
+pub fn in_maintenance(windows: &[MaintenanceWindow], host: &str, now: DateTime<Local>) -> bool {
+    windows.iter().any(|window| window.applies_to(host) && window.schedule.is_active(now))
+}
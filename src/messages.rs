@@ -0,0 +1,649 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{ Duration, Instant };
+use chrono::{ DateTime, Utc };
+use chrono_tz::Tz;
+use log::warn;
+use serde::{ Serialize, Deserialize };
+use crate::checks::{ CheckOutcome, CheckStatus };
+use crate::host_state::format_duration;
+use crate::MAX_MESSAGE_LENGTH;
+
+// hosts and texts remembered by Deduplicator, the oldest is forgotten first beyond this
+const MAX_DEDUP_KEYS: usize = 1000;
+
+// characters that must be escaped in MarkdownV2 text
+const MD_V2_SPECIAL: &[char] = &[
+    '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!', '\\',
+];
+
+// optional custom wording for notifications, unset templates use the built in messages
+// placeholders: {host}, {time}, {duration}, {output} (ping output), {latency}, {threshold}, {loss}
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct Templates {
+    pub offline_template: Option<String>,
+    pub recovered_template: Option<String>,
+    pub still_offline_template: Option<String>,
+    pub degraded_template: Option<String>,
+    pub latency_normal_template: Option<String>,
+    pub unhealthy_template: Option<String>,
+    pub packet_loss_template: Option<String>,
+    pub packet_loss_normal_template: Option<String>,
+    // reply to /add with the result of the first check, {status} is ONLINE, OFFLINE, UNHEALTHY or UNKNOWN
+    pub host_added_template: Option<String>,
+    // {hosts} (comma separated) and {count} for several hosts going offline in the same check cycle
+    pub offline_batch_template: Option<String>,
+    // {count} state changes within {duration}, alerts for the host are held back until it stabilizes
+    pub flapping_template: Option<String>,
+    // flapping host stopped changing state, {status} is ONLINE or OFFLINE
+    pub flap_ended_template: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // host recovered, latency back to normal
+    #[default]
+    Info,
+    // host degraded
+    Warning,
+    // host offline
+    Critical,
+}
+
+// chats that get the notifications of a severity instead of the alert chat, [severity_chats] in config.toml
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct SeverityChats {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub info: Vec<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warning: Vec<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub critical: Vec<i64>,
+}
+
+impl SeverityChats {
+    pub fn chats(&self, severity: Severity) -> &[i64] {
+        match severity {
+            Severity::Info => &self.info,
+            Severity::Warning => &self.warning,
+            Severity::Critical => &self.critical,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    // whole notification including command output and notes
+    #[default]
+    Full,
+    // first line of the notification only
+    Brief,
+}
+
+// what a chat wants to receive, set with /prefs
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct ChatPreferences {
+    // notifications below this severity are not sent to the chat
+    pub min_severity: Severity,
+    pub verbosity: Verbosity,
+}
+
+impl ChatPreferences {
+    // text to send to the chat, None if the chat doesn't want the notification
+    pub fn apply(&self, notification: &Notification, message: &str) -> Option<String> {
+        if notification.severity < self.min_severity {
+            return None;
+        }
+        Some(match self.verbosity {
+            Verbosity::Full => message.to_string(),
+            Verbosity::Brief => message.lines().next().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+// prefix put in front of notifications of each severity, empty prefix sends the message as is
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SeverityPrefixes {
+    pub info: String,
+    pub warning: String,
+    pub critical: String,
+}
+impl Default for SeverityPrefixes {
+    fn default() -> Self {
+        SeverityPrefixes {
+            info: "✅".to_string(),
+            warning: "⚠️".to_string(),
+            critical: "🚨".to_string(),
+        }
+    }
+}
+
+impl SeverityPrefixes {
+    pub fn apply(&self, notification: &Notification) -> String {
+        let prefix = match notification.severity {
+            Severity::Info => &self.info,
+            Severity::Warning => &self.warning,
+            Severity::Critical => &self.critical,
+        };
+        if prefix.is_empty() {
+            notification.text.clone()
+        } else {
+            format!("{} {}", prefix, notification.text)
+        }
+    }
+}
+
+// formatted notification produced by the monitoring loop
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub severity: Severity,
+    pub text: String,
+    // about a host marked critical with /critical, sent even when silenced or in quiet hours
+    pub critical_host: bool,
+    // host changes the notification reports, one webhook request is sent for each
+    pub events: Vec<HostEvent>,
+}
+
+// change of a single host for webhooks
+#[derive(Debug, Clone)]
+pub struct HostEvent {
+    pub host: String,
+    // eg. "offline", "online", "degraded"
+    pub status: &'static str,
+    pub time: DateTime<Utc>,
+}
+
+impl Notification {
+    pub fn new(severity: Severity, text: String) -> Self {
+        Notification { severity, text, critical_host: false, events: Vec::new() }
+    }
+
+    pub fn with_event(mut self, host: &str, status: &'static str) -> Self {
+        self.events.push(HostEvent { host: host.to_string(), status, time: Utc::now() });
+        self
+    }
+}
+
+// drops a host event repeating the last one delivered for that host within the window, a safety net against
+// alert storms, notifications that aren't about a host are compared by their text
+pub struct Deduplicator {
+    window: Duration,
+    // status of the last event let through for each host and when
+    last_status: HashMap<String, (&'static str, Instant)>,
+    // when each text without host events was last let through
+    sent_texts: HashMap<String, Instant>,
+}
+
+impl Deduplicator {
+    // a zero window lets everything through
+    pub fn new(window: Duration) -> Self {
+        Deduplicator { window, last_status: HashMap::new(), sent_texts: HashMap::new() }
+    }
+
+    // true if every event repeats the last one let through for its host within the window,
+    // otherwise the notification is remembered, still offline reminders are already spaced by
+    // reminder_interval and never dropped
+    pub fn is_duplicate(&mut self, notification: &Notification) -> bool {
+        if self.window.is_zero() {
+            return false;
+        }
+        let now = Instant::now();
+        self.last_status.retain(|_, (_, sent)| now.duration_since(*sent) < self.window);
+        self.sent_texts.retain(|_, sent| now.duration_since(*sent) < self.window);
+        if notification.events.is_empty() {
+            if self.sent_texts.contains_key(&notification.text) {
+                return true;
+            }
+            forget_oldest(&mut self.sent_texts, |sent| *sent);
+            self.sent_texts.insert(notification.text.clone(), now);
+            return false;
+        }
+        let repeated = notification.events.iter().all(|event| {
+            event.status != "still_offline" &&
+                self.last_status.get(&event.host).is_some_and(|(status, _)| *status == event.status)
+        });
+        if repeated {
+            return true;
+        }
+        for event in &notification.events {
+            if !self.last_status.contains_key(&event.host) {
+                forget_oldest(&mut self.last_status, |(_, sent)| *sent);
+            }
+            self.last_status.insert(event.host.clone(), (event.status, now));
+        }
+        false
+    }
+}
+
+// make room for one more key once MAX_DEDUP_KEYS are remembered
+fn forget_oldest<V>(map: &mut HashMap<String, V>, sent: impl Fn(&V) -> Instant) {
+    if
+        map.len() >= MAX_DEDUP_KEYS &&
+        let Some(oldest) = map
+            .iter()
+            .min_by_key(|(_, value)| sent(value))
+            .map(|(key, _)| key.clone())
+    {
+        map.remove(&oldest);
+    }
+}
+
+// replace each {name} placeholder with its value, unknown placeholders are left as is
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+// timezone for timestamps in messages, set once from the config at startup
+static TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+pub fn set_timezone(timezone: Tz) {
+    if TIMEZONE.set(timezone).is_err() {
+        warn!("Timezone is already set");
+    }
+}
+
+// timestamp in the configured timezone, UTC when unset
+pub fn format_timestamp(time: DateTime<Utc>) -> String {
+    let timezone = TIMEZONE.get().copied().unwrap_or(Tz::UTC);
+    time.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S %Z").to_string()
+}
+
+fn now() -> String {
+    format_timestamp(Utc::now())
+}
+
+// message sent when the monitoring loop detects a host going offline
+pub fn offline_message(templates: &Templates, host: &str, ping_stdout: &str) -> String {
+    match &templates.offline_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("output", ping_stdout),
+                ]
+            ),
+        None => format!("HOST OFFLINE -> STDOUT {}", ping_stdout),
+    }
+}
+
+// single message for several hosts going offline in the same check cycle
+pub fn offline_batch_message(templates: &Templates, hosts: &[String]) -> String {
+    let mut hosts = hosts.to_vec();
+    hosts.sort();
+    let count = hosts.len().to_string();
+    let hosts = hosts.join(", ");
+    match &templates.offline_batch_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("hosts", &hosts),
+                    ("count", &count),
+                    ("time", &now()),
+                ]
+            ),
+        None => format!("HOSTS OFFLINE -> {} hosts went offline: {}", count, hosts),
+    }
+}
+
+// message sent when the monitoring loop detects an offline host answering again
+pub fn recovered_message(
+    templates: &Templates,
+    host: &str,
+    downtime: chrono::Duration,
+    latency: Option<f64>
+) -> String {
+    let duration = format_duration(downtime);
+    let latency = latency.map(|latency| format!("{:.1}", latency));
+    match &templates.recovered_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("duration", &duration),
+                    ("latency", latency.as_deref().unwrap_or("-")),
+                ]
+            ),
+        None =>
+            match latency {
+                Some(latency) =>
+                    format!("HOST ONLINE -> {} was offline for {}, latency {} ms", host, duration, latency),
+                None => format!("HOST ONLINE -> {} was offline for {}", host, duration),
+            }
+    }
+}
+
+// appended to a recovery notification when ports open before the outage are still closed
+pub fn missing_ports_note(text: &str, missing: &[String]) -> String {
+    format!("{}\nPorts open before the outage are closed now: {}", text, missing.join(", "))
+}
+
+// message sent when a service accepts connections but does not respond as expected
+pub fn unhealthy_message(templates: &Templates, host: &str, output: &str) -> String {
+    match &templates.unhealthy_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("output", output),
+                ]
+            ),
+        None => format!("SERVICE UNHEALTHY -> {} {}", host, output),
+    }
+}
+
+// reminder sent while an unacknowledged outage is ongoing
+pub fn still_offline_message(
+    templates: &Templates,
+    host: &str,
+    downtime: chrono::Duration
+) -> String {
+    let duration = format_duration(downtime);
+    match &templates.still_offline_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("duration", &duration),
+                ]
+            ),
+        None => format!("HOST STILL OFFLINE -> {} for {}", host, duration),
+    }
+}
+
+// sent when a host answers slower than latency_threshold_ms
+pub fn degraded_message(templates: &Templates, host: &str, latency: f64, threshold: f64) -> String {
+    let (latency, threshold) = (format!("{:.1}", latency), format!("{:.1}", threshold));
+    match &templates.degraded_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("latency", &latency),
+                    ("threshold", &threshold),
+                ]
+            ),
+        None => format!("HOST DEGRADED -> {} latency {} ms exceeds {} ms", host, latency, threshold),
+    }
+}
+
+// sent when a degraded host answers within latency_threshold_ms again
+pub fn latency_normal_message(templates: &Templates, host: &str, latency: f64) -> String {
+    let latency = format!("{:.1}", latency);
+    match &templates.latency_normal_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("latency", &latency),
+                ]
+            ),
+        None => format!("HOST LATENCY NORMAL -> {} latency {} ms", host, latency),
+    }
+}
+
+// sent when a host loses more ping packets than loss_threshold
+pub fn packet_loss_message(templates: &Templates, host: &str, loss: f64, threshold: f64) -> String {
+    let (loss, threshold) = (format!("{:.0}", loss), format!("{:.0}", threshold));
+    match &templates.packet_loss_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("loss", &loss),
+                    ("threshold", &threshold),
+                ]
+            ),
+        None => format!("HOST DEGRADED -> {} packet loss {}% exceeds {}%", host, loss, threshold),
+    }
+}
+
+// sent once when a host changes state more than flap_threshold times within flap_window
+pub fn flapping_message(templates: &Templates, host: &str, count: usize, window: chrono::Duration) -> String {
+    let (count, duration) = (count.to_string(), format_duration(window));
+    match &templates.flapping_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("count", &count),
+                    ("duration", &duration),
+                ]
+            ),
+        None =>
+            format!(
+                "HOST FLAPPING -> {} changed state {} times in {}, alerts are paused until it is stable",
+                host,
+                count,
+                duration
+            ),
+    }
+}
+
+// sent when a flapping host kept its state for a whole flap_window
+pub fn flap_ended_message(templates: &Templates, host: &str, online: bool) -> String {
+    let status = if online { "ONLINE" } else { "OFFLINE" };
+    match &templates.flap_ended_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("status", status),
+                ]
+            ),
+        None => format!("HOST STABLE -> {} stopped flapping and is {}", host, status),
+    }
+}
+
+// sent when a lossy host loses no more than loss_threshold again
+pub fn packet_loss_normal_message(templates: &Templates, host: &str, loss: f64) -> String {
+    let loss = format!("{:.0}", loss);
+    match &templates.packet_loss_normal_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("loss", &loss),
+                ]
+            ),
+        None => format!("HOST PACKET LOSS NORMAL -> {} packet loss {}%", host, loss),
+    }
+}
+
+// sent once when checking every due host took longer than the interval
+pub fn falling_behind_message(cycle_secs: f64, interval: u64) -> String {
+    format!(
+        "MONITORING CAN'T KEEP UP -> check cycle took {:.0}s > interval {}s, consider increasing ping_interval or the interval of slow hosts",
+        cycle_secs,
+        interval
+    )
+}
+
+// the bot's own machine uses more cpu, memory or disk than its [resources] threshold
+pub fn resource_high_message(host: &str, resource: &str, usage: f64, threshold: f64) -> String {
+    format!("BOT HOST {} HIGH -> {} at {:.0}% (threshold {:.0}%)", resource.to_uppercase(), host, usage, threshold)
+}
+
+pub fn resource_normal_message(host: &str, resource: &str, usage: f64) -> String {
+    format!("BOT HOST {} NORMAL -> {} at {:.0}%", resource.to_uppercase(), host, usage)
+}
+
+// reply to /add, with the state found by checking the new host once
+pub fn host_added_message(templates: &Templates, host: &str, result: &CheckOutcome) -> String {
+    let status = match result.status {
+        CheckStatus::Up => "ONLINE",
+        CheckStatus::Down => "OFFLINE",
+        CheckStatus::Unhealthy => "UNHEALTHY",
+        CheckStatus::Error => "UNKNOWN",
+    };
+    match &templates.host_added_template {
+        Some(template) =>
+            render(
+                template,
+                &[
+                    ("host", host),
+                    ("time", &now()),
+                    ("status", status),
+                    ("output", &result.output),
+                ]
+            ),
+        None =>
+            match result.status {
+                CheckStatus::Up => format!("Added {}, currently ONLINE", host),
+                CheckStatus::Error => format!("Added {}, unable to check it now: {}", host, result.output),
+                _ => format!("Added {}, currently {}. Check the address if it should be reachable.", host, status),
+            }
+    }
+}
+
+// escape dynamic text for use in a MarkdownV2 message
+pub fn escape_md_v2(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if MD_V2_SPECIAL.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// inside pre and code entities only ` and \ need escaping
+fn escape_md_v2_code(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('`', "\\`")
+}
+
+// MarkdownV2 title followed by text in a pre block, lines that don't fit in one message are cut
+pub fn code_block(title: &str, content: &str) -> String {
+    // title, fences and the cut marker
+    let budget = MAX_MESSAGE_LENGTH.saturating_sub(title.len() + 16);
+    let mut body = String::new();
+    for line in content.lines() {
+        let line = escape_md_v2_code(line);
+        if body.len() + line.len() + 1 > budget {
+            body.push_str("...\n");
+            break;
+        }
+        body.push_str(&line);
+        body.push('\n');
+    }
+    format!("{}\n```\n{}```", title, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(host: &str, status: &'static str) -> Notification {
+        Notification::new(Severity::Critical, format!("{} {}", host, status)).with_event(host, status)
+    }
+
+    #[test]
+    fn dedup_drops_repeat_of_last_delivered_status() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(60));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "offline")));
+        assert!(deduplicator.is_duplicate(&event("10.0.0.5", "offline")));
+        // another host is tracked on its own
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.6", "offline")));
+    }
+
+    #[test]
+    fn dedup_keeps_status_that_returns_after_a_change() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(60));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "offline")));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "online")));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "offline")));
+    }
+
+    #[test]
+    fn dedup_keeps_still_offline_reminders() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(60));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "offline")));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "still_offline")));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "still_offline")));
+    }
+
+    #[test]
+    fn dedup_keeps_batch_with_a_new_event() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(60));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "offline")));
+        let batch = event("10.0.0.5", "offline").with_event("10.0.0.6", "offline");
+        assert!(!deduplicator.is_duplicate(&batch));
+        assert!(deduplicator.is_duplicate(&batch));
+    }
+
+    #[test]
+    fn dedup_compares_text_without_events() {
+        let mut deduplicator = Deduplicator::new(Duration::from_secs(60));
+        let notification = Notification::new(Severity::Warning, "cpu usage high".to_string());
+        assert!(!deduplicator.is_duplicate(&notification));
+        assert!(deduplicator.is_duplicate(&notification));
+    }
+
+    #[test]
+    fn dedup_disabled_with_zero_window() {
+        let mut deduplicator = Deduplicator::new(Duration::ZERO);
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "offline")));
+        assert!(!deduplicator.is_duplicate(&event("10.0.0.5", "offline")));
+    }
+
+    #[test]
+    fn escape_md_v2_escapes_every_special_character() {
+        for c in "_*[]()~`>#+-=|{}.!\\".chars() {
+            assert_eq!(escape_md_v2(&c.to_string()), format!("\\{}", c));
+        }
+        assert_eq!(escape_md_v2("10.0.0.5 (Office-Router)"), "10\\.0\\.0\\.5 \\(Office\\-Router\\)");
+        assert_eq!(escape_md_v2("plain text 42"), "plain text 42");
+    }
+
+    #[test]
+    fn escape_md_v2_code_escapes_only_backslash_and_backtick() {
+        assert_eq!(escape_md_v2_code("a\\b`c"), "a\\\\b\\`c");
+        assert_eq!(escape_md_v2_code("_*[]().!"), "_*[]().!");
+    }
+
+    #[test]
+    fn code_block_escapes_content_but_not_title() {
+        let block = code_block("*Scan*", "PORT `22`\nC:\\temp");
+        assert_eq!(block, "*Scan*\n```\nPORT \\`22\\`\nC:\\\\temp\n```");
+    }
+
+    #[test]
+    fn code_block_cuts_lines_that_dont_fit() {
+        let line = "x".repeat(100);
+        let content = vec![line.as_str(); MAX_MESSAGE_LENGTH / 100 + 10].join("\n");
+        let block = code_block("title", &content);
+        assert!(block.len() <= MAX_MESSAGE_LENGTH);
+        assert!(block.ends_with("...\n```"));
+    }
+}
@@ -0,0 +1,38 @@
+use std::net::IpAddr;
+use crate::runner::CommandRunner;
+
+// addresses in the system neighbor (arp) table that answered recently, sorted
+pub async fn arp_table(runner: &dyn CommandRunner) -> Result<Vec<IpAddr>, String> {
+    let output = runner
+        .run("ip", &["neigh".to_string(), "show".to_string()]).await
+        .map_err(|e| format!("unable to run ip neigh => {}", e))?;
+    if !output.success {
+        return Err(format!("ip neigh failed: {}", output.stderr.trim()));
+    }
+    Ok(parse_ip_neigh(&output.stdout))
+}
+
+// lines like "192.168.1.20 dev eth0 lladdr aa:bb:cc:dd:ee:ff STALE",
+// entries without a link layer address never answered and are skipped
+pub fn parse_ip_neigh(output: &str) -> Vec<IpAddr> {
+    let mut addresses = output
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let address = columns.next()?.parse::<IpAddr>().ok()?;
+            let columns = columns.collect::<Vec<_>>();
+            if !columns.contains(&"lladdr") || columns.iter().any(|column| matches!(*column, "FAILED" | "INCOMPLETE")) {
+                return None;
+            }
+            // link local ipv6 addresses can't be pinged without the interface
+            match address {
+                IpAddr::V6(v6) if v6.is_unicast_link_local() => None,
+                address if address.is_multicast() => None,
+                address => Some(address),
+            }
+        })
+        .collect::<Vec<_>>();
+    addresses.sort();
+    addresses.dedup();
+    addresses
+}
@@ -0,0 +1,208 @@
+use std::collections::BTreeSet;
+use serde::{ Serialize, Deserialize };
+use crate::checks::{ CheckOutcome, CheckStatus };
+use crate::runner::CommandRunner;
+
+const NMAP_PATH: &str = "/bin/nmap";
+// quick sweep used by /status
+pub const STATUS_ARGS: [&str; 5] = ["-T3", "-sT", "-Pn", "--host-timeout", "10"];
+// same sweep as a syn scan, faster and leaves no connections in service logs but needs raw sockets
+pub const PRIVILEGED_STATUS_ARGS: [&str; 5] = ["-T3", "-sS", "-Pn", "--host-timeout", "10"];
+// few common ports for monitor_method = "nmap", any answer shows the host is reachable without icmp
+pub const REACHABILITY_ARGS: [&str; 7] = ["-T4", "-sT", "-Pn", "--top-ports", "20", "--host-timeout", "10"];
+pub const PRIVILEGED_REACHABILITY_ARGS: [&str; 7] = ["-T4", "-sS", "-Pn", "--top-ports", "20", "--host-timeout", "10"];
+// service and version detection used by /scanports, much slower than the quick sweep
+pub const SERVICE_SCAN_ARGS: [&str; 4] = ["-sV", "-Pn", "--host-timeout", "120"];
+
+// how nmap gets the raw socket access syn scans need
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanPrivileges {
+    // unprivileged connect scans
+    #[default]
+    None,
+    // nmap runs through passwordless `sudo -n`
+    Sudo,
+    // nmap has cap_net_raw and cap_net_admin set, eg. with setcap, and is told with --privileged
+    Capabilities,
+}
+
+impl ScanPrivileges {
+    pub fn status_args(&self) -> &'static [&'static str] {
+        match self {
+            ScanPrivileges::None => &STATUS_ARGS,
+            ScanPrivileges::Sudo | ScanPrivileges::Capabilities => &PRIVILEGED_STATUS_ARGS,
+        }
+    }
+
+    pub fn reachability_args(&self) -> &'static [&'static str] {
+        match self {
+            ScanPrivileges::None => &REACHABILITY_ARGS,
+            ScanPrivileges::Sudo | ScanPrivileges::Capabilities => &PRIVILEGED_REACHABILITY_ARGS,
+        }
+    }
+
+    // program to run and the arguments that go before the scan arguments
+    fn command(&self) -> (&'static str, Vec<String>) {
+        match self {
+            ScanPrivileges::None => (NMAP_PATH, Vec::new()),
+            ScanPrivileges::Sudo => ("sudo", vec!["-n".to_string(), NMAP_PATH.to_string()]),
+            ScanPrivileges::Capabilities => (NMAP_PATH, vec!["--privileged".to_string()]),
+        }
+    }
+}
+
+// program and arguments that scan runs
+pub fn command_line(args: &[&str], host: &str, privileges: ScanPrivileges) -> (&'static str, Vec<String>) {
+    let (program, mut command_args) = privileges.command();
+    command_args.extend(args.iter().map(|arg| arg.to_string()));
+    // "--" so a host can never be read as an option, eg. "-oN /etc/passwd"
+    command_args.push("--".to_string());
+    command_args.push(host.to_string());
+    (program, command_args)
+}
+
+// run nmap against a single host, returns cleaned output, or an error if nmap couldn't be run
+// or failed, which says nothing about the host
+pub async fn scan(
+    runner: &dyn CommandRunner,
+    args: &[&str],
+    host: &str,
+    privileges: ScanPrivileges
+) -> Result<String, String> {
+    let (program, command_args) = command_line(args, host, privileges);
+    let output = runner
+        .run(program, &command_args).await
+        .map_err(|e| format!("unable to run {} => {}", program, e))?;
+    if !output.success {
+        return Err(format!("{} failed: {}", program, output.stderr.trim()));
+    }
+    Ok(clean_output(&output.stdout))
+}
+
+// true if the scan found the host down or couldn't reach it, nmap failing to run is an error from scan instead
+pub fn host_is_down(output: &str) -> bool {
+    const DOWN_MARKERS: [&str; 4] = ["Host seems down", "(0 hosts up)", "due to host timeout", "Failed to resolve"];
+    DOWN_MARKERS.iter().any(|marker| output.contains(marker))
+}
+
+// with -Pn nmap always calls the host up, it answered only if some port is open or refused the connection
+pub fn host_answered(output: &str) -> bool {
+    if host_is_down(output) {
+        return false;
+    }
+    output.lines().any(|line| {
+        let mut columns = line.split_whitespace();
+        let port_answered = columns.next().is_some_and(|port| port.contains('/')) &&
+            matches!(columns.next(), Some("open" | "closed" | "unfiltered"));
+        port_answered || (line.starts_with("Not shown:") && line.contains("closed"))
+    })
+}
+
+// what a /status scan found on a host, compared by /diff
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostScanResult {
+    pub up: bool,
+    // open ports as nmap prints them, eg. "22/tcp"
+    pub open_ports: BTreeSet<String>,
+}
+
+impl HostScanResult {
+    pub fn from_output(output: &str) -> Self {
+        let open_ports = output
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split_whitespace();
+                let port = columns.next()?;
+                (port.contains('/') && columns.next() == Some("open")).then(|| port.to_string())
+            })
+            .collect();
+        HostScanResult { up: !host_is_down(output), open_ports }
+    }
+}
+
+// scan output of /status as a check result, latency from "Host is up (0.0012s latency).",
+// a scan that couldn't run is an error rather than the host being up or down
+pub fn outcome(scan: &Result<String, String>) -> CheckOutcome {
+    let output = match scan {
+        Ok(output) => output,
+        Err(e) => {
+            return CheckOutcome {
+                status: CheckStatus::Error,
+                output: e.clone(),
+                latency: None,
+                packet_loss: None,
+            };
+        }
+    };
+    let latency = output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Host is up ("))
+        .and_then(|rest| rest.split_once("s latency"))
+        .and_then(|(seconds, _)| seconds.parse::<f64>().ok())
+        .map(|seconds| seconds * 1000.0);
+    CheckOutcome {
+        status: if host_is_down(output) { CheckStatus::Down } else { CheckStatus::Up },
+        output: output.to_string(),
+        latency,
+        packet_loss: None,
+    }
+}
+
+// first line of `nmap --version`, fails if nmap is missing or doesn't run
+pub async fn version(runner: &dyn CommandRunner) -> Result<String, String> {
+    let output = runner
+        .run(NMAP_PATH, &["--version".to_string()]).await
+        .map_err(|e| format!("unable to run {} => {}", NMAP_PATH, e))?;
+    if !output.success {
+        return Err(format!("{} --version failed: {}", NMAP_PATH, output.stderr.trim()));
+    }
+    Ok(output.stdout.lines().next().unwrap_or_default().to_string())
+}
+
+// keep the first max_lines lines of cleaned output, 0 keeps everything
+pub fn truncate_lines(output: &str, max_lines: usize) -> String {
+    let lines = output.lines().collect::<Vec<_>>();
+    if max_lines == 0 || lines.len() <= max_lines {
+        return output.to_string();
+    }
+    format!(
+        "{}\n... ({} more lines, use /scanports for full)",
+        lines[..max_lines].join("\n"),
+        lines.len() - max_lines
+    )
+}
+
+// drop the "Starting Nmap" banner and empty lines
+fn clean_output(output: &str) -> String {
+    output
+        .lines()
+        .filter(|line| !line.starts_with("Starting Nmap"))
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcome_of_failed_scan_is_an_error() {
+        let result = outcome(&Err("unable to run nmap => not found".to_string()));
+        assert_eq!(result.status, CheckStatus::Error);
+        let result = outcome(&Ok("Note: Host seems down.".to_string()));
+        assert_eq!(result.status, CheckStatus::Down);
+        let result = outcome(&Ok("Host is up (0.0012s latency).\n22/tcp open ssh".to_string()));
+        assert_eq!(result.status, CheckStatus::Up);
+        assert_eq!(result.latency, Some(1.2));
+    }
+
+    #[test]
+    fn command_line_ends_options_before_host() {
+        for privileges in [ScanPrivileges::None, ScanPrivileges::Sudo, ScanPrivileges::Capabilities] {
+            let (_, args) = command_line(&STATUS_ARGS, "-oN", privileges);
+            assert_eq!(&args[args.len() - 2..], ["--", "-oN"]);
+        }
+    }
+}
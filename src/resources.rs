@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::path::{ Path, PathBuf };
+use serde::{ Serialize, Deserialize };
+use sysinfo::{ Disks, System };
+
+// usage percentages of the bot's own machine that trigger an alert, [resources] in config.toml, 0 disables
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct ResourceThresholds {
+    // average cpu usage since the previous check cycle
+    pub cpu_percent: f64,
+    pub memory_percent: f64,
+    // the disk holding the hosts file and state.json
+    pub disk_percent: f64,
+}
+
+impl ResourceThresholds {
+    pub fn enabled(&self) -> bool {
+        self.cpu_percent > 0.0 || self.memory_percent > 0.0 || self.disk_percent > 0.0
+    }
+}
+
+// a resource crossed its threshold in either direction
+pub struct ResourceChange {
+    pub resource: &'static str,
+    pub usage: f64,
+    pub threshold: f64,
+    pub high: bool,
+}
+
+// samples the machine once per check cycle and remembers which resources are above their threshold
+pub struct ResourceMonitor {
+    system: System,
+    disks: Disks,
+    // file whose disk is watched
+    path: PathBuf,
+    high: HashSet<&'static str>,
+}
+
+impl ResourceMonitor {
+    pub fn new(path: &Path) -> Self {
+        let mut system = System::new();
+        // cpu usage is measured between two refreshes, this is the first
+        system.refresh_cpu_usage();
+        ResourceMonitor {
+            system,
+            disks: Disks::new_with_refreshed_list(),
+            path: std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+            high: HashSet::new(),
+        }
+    }
+
+    // resources that went above or back below their threshold since the last check
+    pub fn check(&mut self, thresholds: &ResourceThresholds) -> Vec<ResourceChange> {
+        let mut samples = Vec::new();
+        if thresholds.cpu_percent > 0.0 {
+            self.system.refresh_cpu_usage();
+            samples.push(("cpu", self.system.global_cpu_usage() as f64, thresholds.cpu_percent));
+        }
+        if thresholds.memory_percent > 0.0 {
+            self.system.refresh_memory();
+            samples.push(("memory", percent(self.system.used_memory(), self.system.total_memory()), thresholds.memory_percent));
+        }
+        if thresholds.disk_percent > 0.0 && let Some(usage) = self.disk_usage() {
+            samples.push(("disk", usage, thresholds.disk_percent));
+        }
+
+        samples
+            .into_iter()
+            .filter_map(|(resource, usage, threshold)| {
+                let high = usage > threshold;
+                let changed = if high { self.high.insert(resource) } else { self.high.remove(resource) };
+                changed.then_some(ResourceChange { resource, usage, threshold, high })
+            })
+            .collect()
+    }
+
+    // usage of the disk mounted deepest above the watched path
+    fn disk_usage(&mut self) -> Option<f64> {
+        self.disks.refresh(true);
+        self.disks
+            .list()
+            .iter()
+            .filter(|disk| self.path.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+            .map(|disk| percent(disk.total_space() - disk.available_space(), disk.total_space()))
+    }
+}
+
+fn percent(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (used as f64) / (total as f64) * 100.0
+}
+
+// name of the bot's machine in resource alerts
+pub fn host_name() -> String {
+    System::host_name().unwrap_or_else(|| "bot host".to_string())
+}
@@ -0,0 +1,193 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::process::Command;
+
+// what a finished command printed and whether it exited with 0
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+pub type RunFuture<'a> = Pin<Box<dyn Future<Output = std::io::Result<CommandOutput>> + Send + 'a>>;
+
+// runs the external programs the checks depend on, ping, nmap and sudo
+pub trait CommandRunner: Send + Sync {
+    fn run<'a>(&'a self, program: &'a str, args: &'a [String]) -> RunFuture<'a>;
+}
+
+// runs commands as subprocesses
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run<'a>(&'a self, program: &'a str, args: &'a [String]) -> RunFuture<'a> {
+        Box::pin(async move {
+            let output = Command::new(program).args(args).output().await?;
+            Ok(CommandOutput {
+                success: output.status.success(),
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            })
+        })
+    }
+}
+
+// runner shared through AppState, the system runner unless replaced
+#[derive(Clone)]
+pub struct Runner(Arc<dyn CommandRunner>);
+
+impl Runner {
+    #[cfg(any(test, feature = "mock-commands"))]
+    pub fn new(runner: impl CommandRunner + 'static) -> Self {
+        Runner(Arc::new(runner))
+    }
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Runner(Arc::new(SystemRunner))
+    }
+}
+
+impl CommandRunner for Runner {
+    fn run<'a>(&'a self, program: &'a str, args: &'a [String]) -> RunFuture<'a> {
+        self.0.run(program, args)
+    }
+}
+
+// canned output for commands of one program, optionally only for one target
+#[cfg(any(test, feature = "mock-commands"))]
+#[derive(Debug, serde::Deserialize)]
+struct MockResponse {
+    // matched against the file name of the program or of any argument, so "nmap" also matches "sudo -n /bin/nmap"
+    program: String,
+    // last argument, eg. the pinged host, responses with a target win over ones without
+    target: Option<String>,
+    #[serde(default = "default_success")]
+    success: bool,
+    #[serde(default)]
+    stdout: String,
+    #[serde(default)]
+    stderr: String,
+}
+
+#[cfg(any(test, feature = "mock-commands"))]
+fn default_success() -> bool {
+    true
+}
+
+#[cfg(any(test, feature = "mock-commands"))]
+#[derive(Debug, serde::Deserialize)]
+struct MockFile {
+    #[serde(default)]
+    response: Vec<MockResponse>,
+}
+
+// answers commands from a toml file of [[response]] tables instead of running them,
+// the file is read on every command so edits simulate hosts going down and up
+#[cfg(any(test, feature = "mock-commands"))]
+pub struct MockRunner {
+    path: std::path::PathBuf,
+}
+
+#[cfg(any(test, feature = "mock-commands"))]
+impl MockRunner {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        MockRunner { path: path.into() }
+    }
+}
+
+#[cfg(any(test, feature = "mock-commands"))]
+impl CommandRunner for MockRunner {
+    fn run<'a>(&'a self, program: &'a str, args: &'a [String]) -> RunFuture<'a> {
+        Box::pin(async move {
+            let content = tokio::fs::read_to_string(&self.path).await?;
+            let file = toml::from_str::<MockFile>(&content).map_err(std::io::Error::other)?;
+            log::debug!("Mock command: {} {}", program, args.join(" "));
+
+            let file_name = |path: &str| {
+                std::path::Path
+                    ::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            };
+            let names = std::iter
+                ::once(program)
+                .chain(args.iter().map(String::as_str))
+                .map(file_name)
+                .collect::<Vec<_>>();
+            let target = args.last().map(String::as_str);
+            let response = file.response
+                .into_iter()
+                .filter(|response| names.contains(&response.program))
+                .filter(|response| response.target.is_none() || response.target.as_deref() == target)
+                .max_by_key(|response| response.target.is_some())
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("no mock response for {} {}", program, args.join(" "))
+                    )
+                })?;
+            Ok(CommandOutput { success: response.success, stdout: response.stdout, stderr: response.stderr })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSES: &str = r#"
+[[response]]
+program = "ping"
+stdout = "any host"
+
+[[response]]
+program = "ping"
+target = "10.0.0.5"
+success = false
+stdout = "only 10.0.0.5"
+
+[[response]]
+program = "nmap"
+stdout = "nmap output"
+"#;
+
+    fn mock(name: &str) -> MockRunner {
+        let path = std::env::temp_dir().join(format!("notification_bot_{}_{}.toml", name, std::process::id()));
+        std::fs::write(&path, RESPONSES).unwrap();
+        MockRunner::new(path)
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn response_with_target_wins() {
+        let runner = mock("target");
+        let output = runner.run("ping", &args(&["-c", "3", "--", "10.0.0.5"])).await.unwrap();
+        assert!(!output.success);
+        assert_eq!(output.stdout, "only 10.0.0.5");
+        let output = runner.run("ping", &args(&["-c", "3", "--", "10.0.0.6"])).await.unwrap();
+        assert!(output.success);
+        assert_eq!(output.stdout, "any host");
+        std::fs::remove_file(&runner.path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn program_matches_file_name_of_program_or_argument() {
+        let runner = mock("program");
+        let output = runner.run("/usr/bin/nmap", &args(&["-sT", "--", "10.0.0.5"])).await.unwrap();
+        assert_eq!(output.stdout, "nmap output");
+        // sudo -n /usr/bin/nmap
+        let output = runner.run("sudo", &args(&["-n", "/usr/bin/nmap", "-sS", "--", "10.0.0.5"])).await.unwrap();
+        assert_eq!(output.stdout, "nmap output");
+        let e = runner.run("traceroute", &args(&["10.0.0.5"])).await.unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+        std::fs::remove_file(&runner.path).unwrap();
+    }
+}
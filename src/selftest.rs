@@ -0,0 +1,67 @@
+use log::{ info, warn };
+use crate::checks::{ self, CheckStatus };
+use crate::nmap;
+use crate::runner::CommandRunner;
+
+// pinged to check that ping runs with the configured arguments and permissions
+const SELFTEST_PING_HOST: &str = "127.0.0.1";
+
+pub struct SelfTestResult {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+// check that the ping and nmap binaries used by the bot exist and run
+pub async fn run(runner: &dyn CommandRunner, ping_args: &[String]) -> Vec<SelfTestResult> {
+    let ping = match checks::ping(runner, ping_args, SELFTEST_PING_HOST).await {
+        Ok(result) if result.status == CheckStatus::Up => {
+            let latency = result.latency
+                .map(|latency| format!(" in {:.1} ms", latency))
+                .unwrap_or_default();
+            SelfTestResult {
+                name: "ping",
+                ok: true,
+                detail: format!("{} answered{}", SELFTEST_PING_HOST, latency),
+            }
+        }
+        Ok(result) => {
+            SelfTestResult {
+                name: "ping",
+                ok: false,
+                detail: format!(
+                    "{} did not answer, check ping_args and permissions: {}",
+                    SELFTEST_PING_HOST,
+                    result.output.trim()
+                ),
+            }
+        }
+        Err(e) => SelfTestResult { name: "ping", ok: false, detail: format!("unable to run ping => {}", e) },
+    };
+    let nmap = match nmap::version(runner).await {
+        Ok(version) => SelfTestResult { name: "nmap", ok: true, detail: version },
+        Err(e) => SelfTestResult { name: "nmap", ok: false, detail: e },
+    };
+    vec![ping, nmap]
+}
+
+pub fn log(results: &[SelfTestResult]) {
+    for result in results {
+        if result.ok {
+            info!("Self test {} OK -> {}", result.name, result.detail);
+        } else {
+            warn!("Self test {} FAILED -> {}", result.name, result.detail);
+        }
+    }
+}
+
+pub fn report(results: &[SelfTestResult]) -> String {
+    results
+        .iter()
+        .map(|result| {
+            let status = if result.ok { "OK" } else { "FAILED" };
+            format!("{} {}: {}", status, result.name, result.detail)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+use chrono::{ DateTime, Utc };
+use crate::messages::format_timestamp;
+
+#[derive(Debug, Clone)]
+pub struct ScanRecord {
+    pub finished: DateTime<Utc>,
+    // seconds
+    pub duration: f64,
+    pub hosts: usize,
+}
+
+// timing of recent /status scans, kept in memory only
+#[derive(Debug, Default)]
+pub struct ScanStats {
+    // oldest first
+    history: VecDeque<ScanRecord>,
+    total: u64,
+}
+
+impl ScanStats {
+    // keeps the last max_history scans
+    pub fn record(&mut self, duration: f64, hosts: usize, max_history: usize) {
+        self.history.push_back(ScanRecord { finished: Utc::now(), duration, hosts });
+        while self.history.len() > max_history {
+            self.history.pop_front();
+        }
+        self.total += 1;
+    }
+
+    pub fn summary(&self) -> String {
+        let Some(last) = self.history.back() else {
+            return "No scans run yet.".to_string();
+        };
+        let durations = self.history.iter().map(|record| record.duration);
+        let min = durations.clone().fold(f64::INFINITY, f64::min);
+        let max = durations.clone().fold(0.0, f64::max);
+        let average = durations.sum::<f64>() / (self.history.len() as f64);
+        format!(
+            "Scans run: {}\nLast scan: {:.2}s for {} hosts at {}\nLast {} scans: avg {:.2}s, min {:.2}s, max {:.2}s",
+            self.total,
+            last.duration,
+            last.hosts,
+            format_timestamp(last.finished),
+            self.history.len(),
+            average,
+            min,
+            max
+        )
+    }
+}
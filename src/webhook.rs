@@ -0,0 +1,61 @@
+use serde::{ Serialize, Deserialize };
+use crate::messages::{ self, HostEvent };
+
+// generic http endpoint notified of every host event, [[webhooks]] in config.toml
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    // request body with {host}, {status}, {time} and {message} placeholders, shaped for the receiving system
+    #[serde(default = "default_template")]
+    pub template: String,
+    // values are escaped as json strings when this contains "json"
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+}
+
+fn default_template() -> String {
+    r#"{"host": "{host}", "status": "{status}", "time": "{time}", "message": "{message}"}"#.to_string()
+}
+
+fn default_content_type() -> String {
+    "application/json".to_string()
+}
+
+impl WebhookConfig {
+    // body for one event, message is the full notification text
+    pub fn payload(&self, event: &HostEvent, message: &str) -> String {
+        let json = self.content_type.to_ascii_lowercase().contains("json");
+        let escape = |value: &str| {
+            if !json {
+                return value.to_string();
+            }
+            // placeholders sit inside quotes in the template, so the quotes of the json string are dropped
+            let quoted = serde_json::to_string(value).unwrap_or_default();
+            quoted[1..quoted.len() - 1].to_string()
+        };
+        let time = event.time.to_rfc3339();
+        messages::render(
+            &self.template,
+            &[
+                ("host", &escape(&event.host)),
+                ("status", &escape(event.status)),
+                ("time", &escape(&time)),
+                ("message", &escape(message)),
+            ]
+        )
+    }
+}
+
+// post an event to a webhook
+pub async fn send(client: &reqwest::Client, webhook: &WebhookConfig, body: String) -> Result<(), String> {
+    let response = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, &webhook.content_type)
+        .body(body)
+        .send().await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("webhook returned status {}", response.status()));
+    }
+    Ok(())
+}